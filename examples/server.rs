@@ -0,0 +1,53 @@
+#![allow(non_snake_case, unused_variables)]
+
+use TCP::{Interface, IPAddress, CaptureFormat};
+use std::fs::File;
+use std::io::{self, BufWriter, Read};
+use std::time::Duration;
+
+/// Usage: `server [ss] [capture <file.pcapng>]`
+///
+/// With `ss` the connection table is printed every second, like `ss -tan`.
+/// With `capture` every packet on the interface is written to a pcapng file.
+fn main() -> io::Result<()> {
+    let srcIP = IPAddress::new(10, 12, 0, 1);
+    let dstIP = IPAddress::new(10, 12, 0, 2);
+    let mut interface = Interface::new("tun0", srcIP, dstIP)?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(index) = args.iter().position(|arg| arg == "capture") {
+        let path = args.get(index + 1).map(String::as_str).unwrap_or("capture.pcapng");
+        interface.start_capture(BufWriter::new(File::create(path)?), CaptureFormat::PcapNg)?;
+    }
+    let mut listener = interface.bind((IPAddress::UNSPECIFIED, 9000))?;
+    let thread = std::thread::spawn(move || {
+        // This handles single Connection at a time. Other connections wait
+        while let Ok(mut stream) = listener.accept() {
+            // New Connecton
+            let mut buffer = [0u8; 1000];
+            println!("New Connection");
+            if let Ok(len) = stream.read(&mut buffer) {
+                println!("Recieved Request : {}", String::from_utf8_lossy(&buffer[..len]));
+            }
+            let ret = stream.read(&mut buffer);
+            match ret {
+                Ok(len) => {
+                    println!("Recieved Request 2 : {}", std::str::from_utf8(&buffer[..]).unwrap());
+                },
+                Err(error) => {
+                    println!("Error Occured");
+                }
+            }
+            // stream.write(b"Hello From Server");
+        }
+    });
+
+    if args.iter().any(|arg| arg == "ss") {
+        loop {
+            std::thread::sleep(Duration::from_secs(1));
+            println!("{}", interface.connections());
+        }
+    }
+
+    thread.join().unwrap();
+    Ok(())
+}
@@ -0,0 +1,56 @@
+#![no_main]
+//! Feeds arbitrary packets into a stack with a listener and reads whatever it accepts.
+//!
+//! Input is a sequence of `<control> <len> <len bytes>` records. Bit 0 of `control`
+//! sends the bytes as a raw IP packet, otherwise they are wrapped in a valid IP header
+//! from the peer to the stack and get a correct TCP checksum. The remaining bits are how long to run the stack
+//! afterwards, in units of 10ms, so timers get exercised too.
+use libfuzzer_sys::fuzz_target;
+use std::io::Read;
+use std::time::Duration;
+use TCP::{IPAddress, Keepalive, Simulation};
+use TCP::Parser::{IPHeader, IPProtocol, TcpSegment};
+
+const LOCAL: IPAddress = IPAddress { bytes: [10, 0, 0, 1] };
+const REMOTE: IPAddress = IPAddress { bytes: [10, 0, 0, 2] };
+
+fuzz_target!(|data: &[u8]| {
+    let mut simulation = Simulation::new(0);
+    let mut interface = simulation.add_interface(LOCAL).unwrap();
+    let peer = simulation.add_endpoint(REMOTE).unwrap();
+    interface.set_keepalive(Some(Keepalive { idle: Duration::from_secs(1), interval: Duration::from_millis(500), probes: 2 }));
+    let mut listener = interface.bind((LOCAL, 9000)).unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let mut streams = Vec::new();
+
+    let mut input = data;
+    while input.len() >= 2 {
+        let (control, len) = (input[0], input[1] as usize);
+        let bytes = &input[2..input.len().min(2 + len)];
+        input = &input[2 + bytes.len()..];
+
+        let packet = if control & 1 == 1 {
+            bytes.to_vec()
+        } else {
+            let mut packet = vec![0u8; 20 + bytes.len()];
+            IPHeader::new(REMOTE, LOCAL, IPProtocol::Tcp, 64, bytes.len() as u16).serialize(&mut packet);
+            packet[20..].copy_from_slice(bytes);
+            if bytes.len() >= 18 {
+                TcpSegment::newUnchecked(&mut packet[20..]).fillChecksum(REMOTE, LOCAL);
+            }
+            packet
+        };
+        peer.send(&packet);
+        simulation.advance(Duration::from_millis(10 * (control >> 1) as u64));
+
+        while let Ok(stream) = listener.accept() {
+            stream.set_nonblocking(true).unwrap();
+            streams.push(stream);
+        }
+        for stream in &mut streams {
+            let _ = stream.read(&mut [0u8; 1500]);
+        }
+        while peer.recv().is_some() {}
+    }
+    simulation.advance(Duration::from_secs(5));
+});
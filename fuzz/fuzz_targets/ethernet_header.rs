@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use TCP::Parser::EthernetHeader;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(header) = EthernetHeader::from(data) {
+        assert!(header.size() <= data.len());
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use TCP::Parser::{IPHeader, IPOption};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(header) = IPHeader::from(data) {
+        // Whatever parses must be safe to slice with
+        assert!(header.size() >= 20);
+        assert!(header.size() <= header.totalLength as usize);
+        assert!(header.totalLength as usize <= data.len());
+        // Options were checked while parsing
+        let options = header.getOptions().unwrap();
+        let mut buffer = [0u8; 40];
+        let _ = IPOption::serialize(&options, &mut buffer);
+    }
+});
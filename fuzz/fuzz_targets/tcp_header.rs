@@ -0,0 +1,17 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use TCP::Parser::{TCPHeader, TcpSegment};
+
+fuzz_target!(|data: &[u8]| {
+    let segment = TcpSegment::new(data);
+    assert_eq!(segment.is_ok(), TCPHeader::from(data).is_ok());
+    if let Ok(header) = TCPHeader::from(data) {
+        // Whatever parses must be safe to slice the payload with
+        assert!(header.size() >= 20);
+        assert!(header.size() <= data.len());
+        let _ = header.flags();
+    }
+    if let Ok(segment) = segment {
+        let _ = (segment.options(), segment.payload(), segment.flags());
+    }
+});
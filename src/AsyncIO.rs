@@ -0,0 +1,156 @@
+use crate::{TCPStream, TCPListener, TcpError};
+use futures_io::{AsyncRead, AsyncWrite};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// ================================================
+///                  AsyncTCPStream
+/// ================================================
+/// `futures::io::AsyncRead`/`AsyncWrite` implementation over a `TCPStream`.
+/// Tasks are woken from `packetLoop` so it does not depend on any particular runtime.
+pub struct AsyncTCPStream {
+    stream: TCPStream,
+}
+
+impl From<TCPStream> for AsyncTCPStream {
+    fn from(stream: TCPStream) -> Self {
+        AsyncTCPStream { stream }
+    }
+}
+
+impl AsyncTCPStream {
+    pub fn get_ref(&self) -> &TCPStream { &self.stream }
+
+    pub fn into_inner(self) -> TCPStream { self.stream }
+}
+
+impl AsyncRead for AsyncTCPStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let active = &self.stream.connection;
+        let mut connection = active.connection.lock().unwrap();
+        if let Some(error) = &connection.error {
+            return Poll::Ready(Err(error.clone().into()));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        if !connection.incoming.is_empty() {
+            return Poll::Ready(Ok(connection.readIncoming(buf)));
+        }
+        if connection.finReceived {
+            // End of file
+            return Poll::Ready(Ok(0));
+        }
+        if !connection.isHandled {
+            return Poll::Ready(Err(TcpError::ConnectionAborted.into()));
+        }
+
+        // Register waker while still holding `connection`, so that no packet is missed
+        *active.readWaker.lock().unwrap() = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for AsyncTCPStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let active = &self.stream.connection;
+        let mut connection = active.connection.lock().unwrap();
+        if let Some(error) = &connection.error {
+            return Poll::Ready(Err(error.clone().into()));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let len = connection.writeOutgoing(buf);
+        if len > 0 {
+            return Poll::Ready(Ok(len));
+        }
+
+        *active.writeWaker.lock().unwrap() = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Completes once all bytes written are acknowledged
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let active = &self.stream.connection;
+        let connection = active.connection.lock().unwrap();
+        if let Some(error) = &connection.error {
+            return Poll::Ready(Err(error.clone().into()));
+        }
+        if connection.outgoing.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+
+        *active.writeWaker.lock().unwrap() = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// TODO: Send a fin once `TCPStream::close` is supported.
+    ///       Until then this only waits for outstanding data to be acknowledged.
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+/// ================================================
+///                 AsyncTCPListener
+/// ================================================
+pub struct AsyncTCPListener {
+    listener: TCPListener,
+}
+
+impl From<TCPListener> for AsyncTCPListener {
+    fn from(listener: TCPListener) -> Self {
+        AsyncTCPListener { listener }
+    }
+}
+
+impl AsyncTCPListener {
+    /// Resolves to the next established connection
+    pub fn accept(&mut self) -> Accept<'_> {
+        Accept { listener: self }
+    }
+
+    pub fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<AsyncTCPStream>> {
+        let listener = &self.listener;
+        let mut pendingQueue = listener.pending.pendingQueue.lock().unwrap();
+        if listener.terminate {
+            return Poll::Ready(Err(TcpError::ConnectionAborted.into()));
+        }
+        if let Some(error) = listener.deviceError() {
+            return Poll::Ready(Err(error.into()));
+        }
+
+        match pendingQueue.pop_front() {
+            Some(connection) => {
+                drop(pendingQueue);
+                Poll::Ready(Ok(AsyncTCPStream::from(listener.stream(connection))))
+            },
+            None => {
+                *listener.pending.waker.lock().unwrap() = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    pub fn get_ref(&self) -> &TCPListener { &self.listener }
+
+    pub fn into_inner(self) -> TCPListener { self.listener }
+}
+
+/// Future returned by `AsyncTCPListener::accept`
+pub struct Accept<'a> {
+    listener: &'a mut AsyncTCPListener,
+}
+
+impl Future for Accept<'_> {
+    type Output = io::Result<AsyncTCPStream>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.listener.poll_accept(cx)
+    }
+}
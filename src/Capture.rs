@@ -0,0 +1,138 @@
+extern crate byteorder;
+use byteorder::{ByteOrder, LittleEndian};
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Write};
+use std::time::UNIX_EPOCH;
+use crate::Time::Clock;
+
+/// ===> CONSTANTS
+/// Packets on the interface are raw IP packets without a link layer header
+pub(crate) const LINKTYPE_RAW             : u32 = 101;
+const SNAPLEN                             : u32 = 65535;
+
+pub(crate) const PCAP_MAGIC               : u32 = 0xA1B2_C3D4;
+pub(crate) const PCAPNG_SHB               : u32 = 0x0A0D_0D0A;
+pub(crate) const PCAPNG_BYTE_ORDER        : u32 = 0x1A2B_3C4D;
+pub(crate) const PCAPNG_IDB               : u32 = 0x0000_0001;
+pub(crate) const PCAPNG_EPB               : u32 = 0x0000_0006;
+pub(crate) const PCAPNG_OPT_EPB_FLAGS     : u16 = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// Classic libpcap format. Has no place to store direction of packet.
+    Pcap,
+    /// pcapng with direction stored in `epb_flags` of every packet
+    PcapNg,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// ================================================
+///                   Capture Sink
+/// ================================================
+/// Writes packets crossing an interface to a pcap/pcapng stream
+/// which can be opened in Wireshark or tcpdump.
+pub(crate) struct Capture {
+    writer: Box<dyn Write + Send>,
+    format: CaptureFormat,
+    clock : Clock,
+}
+
+impl Debug for Capture {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Capture").field("format", &self.format).finish()
+    }
+}
+
+impl Capture {
+    /// Writes file headers to `writer`
+    pub(crate) fn new(mut writer: Box<dyn Write + Send>, format: CaptureFormat, clock: Clock) -> io::Result<Self> {
+        match format {
+            CaptureFormat::Pcap => {
+                let mut header = [0u8; 24];
+                LittleEndian::write_u32(&mut header[0..], PCAP_MAGIC);
+                LittleEndian::write_u16(&mut header[4..], 2);      // Major Version
+                LittleEndian::write_u16(&mut header[6..], 4);      // Minor Version
+                // Timezone offset and timestamp accuracy are always 0
+                LittleEndian::write_u32(&mut header[16..], SNAPLEN);
+                LittleEndian::write_u32(&mut header[20..], LINKTYPE_RAW);
+                writer.write_all(&header)?;
+            },
+            CaptureFormat::PcapNg => {
+                // Section Header Block
+                let mut shb = [0u8; 28];
+                LittleEndian::write_u32(&mut shb[0..], PCAPNG_SHB);
+                LittleEndian::write_u32(&mut shb[4..], 28);
+                LittleEndian::write_u32(&mut shb[8..], PCAPNG_BYTE_ORDER);
+                LittleEndian::write_u16(&mut shb[12..], 1);        // Major Version
+                LittleEndian::write_u16(&mut shb[14..], 0);        // Minor Version
+                LittleEndian::write_i64(&mut shb[16..], -1);       // Section length not known
+                LittleEndian::write_u32(&mut shb[24..], 28);
+                writer.write_all(&shb)?;
+
+                // Interface Description Block. Timestamps use default resolution of microseconds.
+                let mut idb = [0u8; 20];
+                LittleEndian::write_u32(&mut idb[0..], PCAPNG_IDB);
+                LittleEndian::write_u32(&mut idb[4..], 20);
+                LittleEndian::write_u16(&mut idb[8..], LINKTYPE_RAW as u16);
+                LittleEndian::write_u32(&mut idb[12..], SNAPLEN);
+                LittleEndian::write_u32(&mut idb[16..], 20);
+                writer.write_all(&idb)?;
+            }
+        }
+        Ok(Capture { writer, format, clock })
+    }
+
+    pub(crate) fn record(&mut self, direction: Direction, packet: &[u8]) -> io::Result<()> {
+        let micros = self.clock.wallTime().duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64;
+        let len = packet.len() as u32;
+
+        match self.format {
+            CaptureFormat::Pcap => {
+                let mut header = [0u8; 16];
+                LittleEndian::write_u32(&mut header[0..], (micros / 1_000_000) as u32);
+                LittleEndian::write_u32(&mut header[4..], (micros % 1_000_000) as u32);
+                LittleEndian::write_u32(&mut header[8..], len);
+                LittleEndian::write_u32(&mut header[12..], len);
+                self.writer.write_all(&header)?;
+                self.writer.write_all(packet)?;
+            },
+            CaptureFormat::PcapNg => {
+                // Enhanced Packet Block
+                //   header(28) + data padded to 32 bits + epb_flags(8) + end of options(4) + length(4)
+                let padding = (4 - packet.len() % 4) % 4;
+                let total = 28 + packet.len() + padding + 8 + 4 + 4;
+
+                let mut header = [0u8; 28];
+                LittleEndian::write_u32(&mut header[0..], PCAPNG_EPB);
+                LittleEndian::write_u32(&mut header[4..], total as u32);
+                LittleEndian::write_u32(&mut header[8..], 0);      // Interface ID
+                LittleEndian::write_u32(&mut header[12..], (micros >> 32) as u32);
+                LittleEndian::write_u32(&mut header[16..], micros as u32);
+                LittleEndian::write_u32(&mut header[20..], len);
+                LittleEndian::write_u32(&mut header[24..], len);
+                self.writer.write_all(&header)?;
+                self.writer.write_all(packet)?;
+                self.writer.write_all(&[0u8; 3][..padding])?;
+
+                let mut trailer = [0u8; 16];
+                LittleEndian::write_u16(&mut trailer[0..], PCAPNG_OPT_EPB_FLAGS);
+                LittleEndian::write_u16(&mut trailer[2..], 4);
+                let flags = match direction { Direction::In => 0b01, Direction::Out => 0b10 };
+                LittleEndian::write_u32(&mut trailer[4..], flags);
+                // trailer[8..12] is opt_endofopt
+                LittleEndian::write_u32(&mut trailer[12..], total as u32);
+                self.writer.write_all(&trailer)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
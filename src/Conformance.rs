@@ -0,0 +1,565 @@
+use crate::{Interface, Keepalive, Simulation, Endpoint, TCPListener, TCPStream};
+use crate::Netstat::stateName;
+use crate::Parser::{IPAddress, IPHeader, IPProtocol, TCPHeader, TcpOptionElement, TcpSegment};
+use std::fmt::{self, Display, Formatter};
+use std::io::{ErrorKind, Read, Write};
+use std::time::Duration;
+
+/// ===> CONSTANTS
+/// Addresses and port of the stack under test and of the scripted peer
+pub const SCRIPT_LOCAL  : IPAddress = IPAddress { bytes: [192, 168, 0, 1] };
+pub const SCRIPT_REMOTE : IPAddress = IPAddress { bytes: [192, 0, 2, 1] };
+pub const SCRIPT_REMOTE_PORT : u16 = 40000;
+/// Local port until a script binds another one
+const DEFAULT_LOCAL_PORT : u16 = 8080;
+/// Window of injected segments without `win`
+const DEFAULT_WINDOW : u16 = 65535;
+const DEFAULT_TOLERANCE : Duration = Duration::from_millis(10);
+
+/// ================================================
+///                      Script
+/// ================================================
+/// Packetdrill style test of the stack's behaviour on the wire. Every line is a
+/// time followed by an event:
+///
+/// ```text
+/// 0      bind 8080
+/// 0.1    < S 100:100(0) win 1024 <mss 1460,nop,wscale 7>
+/// +0     > S. 0:0(0) ack 101 win 10
+/// +0.1   < . 101:101(0) ack 1
+/// +0     accept
+/// +0     state ESTAB
+/// ```
+///
+/// Times are seconds, absolute or relative to the previous line with `+`.
+/// `<` injects a segment from the peer, `>` expects the stack to send one
+/// within the tolerance (default 10ms) of its time. Segments are written as
+/// flags (`S`, `F`, `R`, `P`, `U`, `E`, `W`, `.` for ACK), sequence range
+/// `start:end(len)`, then optional `ack`, `win` and options in angle brackets.
+/// An expected segment only checks the fields that are written out.
+/// Any other segment from the stack fails the script.
+///
+/// Commands: `bind <port>`, `accept`, `read <n>`, `read eof`, `read error <kind>`, `write <n>`,
+/// `abort`, `keepalive <idle> <interval> <probes>`, `keepalive off`,
+/// `state <name>` (as printed by `ss`) and `tolerance <seconds>`.
+/// Comments start with `#`.
+///
+/// Scripts run in a `Simulation` with a zero latency link, so they take
+/// milliseconds regardless of the times they span.
+#[derive(Debug, Clone)]
+pub struct Script {
+    events: Vec<ScriptEvent>,
+}
+
+/// Failure to parse or run a script, with the line it happened on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line    : usize,
+    pub message : String,
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[derive(Debug, Clone)]
+struct ScriptEvent {
+    line   : usize,
+    time   : Duration,
+    action : Action,
+}
+
+#[derive(Debug, Clone)]
+enum Action {
+    Inject(Segment),
+    Expect(Segment),
+    Bind(u16),
+    Accept,
+    Read(usize),
+    ReadEof,
+    ReadError(ErrorKind),
+    Write(usize),
+    Abort,
+    Keepalive(Option<Keepalive>),
+    State(String),
+    Tolerance(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    /// Control bits as in `TCPHeader::getControlBits`
+    flags   : u8,
+    start   : u32,
+    len     : u32,
+    ack     : Option<u32>,
+    window  : Option<u16>,
+    options : Option<Vec<TcpOptionElement>>,
+}
+
+/// Flag letters in the order they are printed. ACK is `.` and printed last.
+const FLAG_LETTERS: [(char, u8); 8] = [
+    ('S', 0b0000_0010), ('F', 0b0000_0001), ('R', 0b0000_0100), ('P', 0b0000_1000),
+    ('U', 0b0010_0000), ('E', 0b0100_0000), ('W', 0b1000_0000), ('.', 0b0001_0000),
+];
+
+impl Display for Segment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (letter, bit) in FLAG_LETTERS.iter() {
+            if self.flags & bit != 0 {
+                write!(f, "{}", letter)?;
+            }
+        }
+        write!(f, " {}:{}({})", self.start, self.start.wrapping_add(self.len), self.len)?;
+        if let Some(ack) = self.ack {
+            write!(f, " ack {}", ack)?;
+        }
+        if let Some(window) = self.window {
+            write!(f, " win {}", window)?;
+        }
+        if let Some(options) = &self.options {
+            let options: Vec<String> = options.iter().map(optionName).collect();
+            write!(f, " <{}>", options.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl Script {
+    pub fn parse(text: &str) -> Result<Script, ScriptError> {
+        let mut events = Vec::new();
+        let mut previous = Duration::from_secs(0);
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| ScriptError { line: index + 1, message };
+
+            let (time, rest) = line.split_at(line.find(char::is_whitespace).unwrap_or(line.len()));
+            let time = parseTime(time, previous).map_err(error)?;
+            if time < previous {
+                return Err(error(format!("time goes backwards from {:?} to {:?}", previous, time)));
+            }
+            previous = time;
+            let action = parseAction(rest.trim()).map_err(error)?;
+            events.push(ScriptEvent { line: index + 1, time, action });
+        }
+        Ok(Script { events })
+    }
+
+    /// Run the script against a fresh stack. Fails on the first event
+    /// that does not happen as written.
+    pub fn run(&self) -> Result<(), ScriptError> {
+        let mut simulation = Simulation::new(0);
+        simulation.set_latency(Duration::from_secs(0), Duration::from_secs(0));
+        let interface = simulation.add_interface(SCRIPT_LOCAL).map_err(|error| ScriptError { line: 0, message: error.to_string() })?;
+        let peer = simulation.add_endpoint(SCRIPT_REMOTE).map_err(|error| ScriptError { line: 0, message: error.to_string() })?;
+        let mut runner = Runner {
+            simulation, interface, peer,
+            listener: None,
+            stream: None,
+            pending: None,
+            localPort: DEFAULT_LOCAL_PORT,
+            tolerance: DEFAULT_TOLERANCE,
+        };
+
+        for event in &self.events {
+            runner.run(event).map_err(|message| ScriptError { line: event.line, message })?;
+        }
+        // Nothing more may be sent after the last event
+        if let Some(last) = self.events.last() {
+            let end = last.time + runner.tolerance;
+            runner.idle(end).map_err(|message| ScriptError { line: last.line, message })?;
+        }
+        Ok(())
+    }
+}
+
+/// ================================================
+///                      Runner
+/// ================================================
+struct Runner {
+    simulation : Simulation,
+    interface  : Interface,
+    peer       : Endpoint,
+    listener   : Option<TCPListener>,
+    stream     : Option<TCPStream>,
+    /// Packet received by the peer but not yet matched
+    pending    : Option<(Duration, Vec<u8>)>,
+    localPort  : u16,
+    tolerance  : Duration,
+}
+
+impl Runner {
+    fn run(&mut self, event: &ScriptEvent) -> Result<(), String> {
+        if let Action::Expect(expected) = &event.action {
+            return self.expect(event.time, expected);
+        }
+
+        self.idle(event.time)?;
+        match &event.action {
+            Action::Inject(segment) => {
+                let packet = self.build(segment)?;
+                self.peer.send(&packet);
+            },
+            Action::Expect(_) => unreachable!(),
+            Action::Bind(port) => {
+                let mut listener = self.interface.bind((SCRIPT_LOCAL, *port)).map_err(|error| format!("bind failed: {}", error))?;
+                listener.set_nonblocking(true).unwrap();
+                self.localPort = *port;
+                self.listener = Some(listener);
+            },
+            Action::Accept => {
+                let listener = self.listener.as_mut().ok_or("accept without bind")?;
+                let stream = listener.accept().map_err(|_| "no connection to accept")?;
+                stream.set_nonblocking(true).unwrap();
+                self.stream = Some(stream);
+            },
+            Action::Read(expected) => {
+                let stream = self.stream.as_mut().ok_or("read without accept")?;
+                let mut buf = vec![0u8; *expected];
+                let mut total = 0;
+                while total < *expected {
+                    match stream.read(&mut buf[total..]) {
+                        Ok(0) => break,
+                        Ok(n) => total += n,
+                        Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                        Err(error) => return Err(format!("read failed: {}", error)),
+                    }
+                }
+                if total != *expected {
+                    return Err(format!("read {} bytes, expected {}", total, expected));
+                }
+            },
+            Action::ReadEof => {
+                let stream = self.stream.as_mut().ok_or("read without accept")?;
+                match stream.read(&mut [0u8; 1500]) {
+                    Ok(0) => {},
+                    Ok(n) => return Err(format!("read {} bytes, expected end of file", n)),
+                    Err(error) => return Err(format!("read failed with {:?}, expected end of file", error.kind())),
+                }
+            },
+            Action::ReadError(kind) => {
+                let stream = self.stream.as_mut().ok_or("read without accept")?;
+                match stream.read(&mut [0u8; 1500]) {
+                    Err(error) if error.kind() == *kind => {},
+                    Err(error) => return Err(format!("read failed with {:?}, expected {:?}", error.kind(), kind)),
+                    Ok(n) => return Err(format!("read {} bytes, expected {:?}", n, kind)),
+                }
+            },
+            Action::Write(len) => {
+                let stream = self.stream.as_mut().ok_or("write without accept")?;
+                let written = stream.write(&vec![b'x'; *len]).map_err(|error| format!("write failed: {}", error))?;
+                if written != *len {
+                    return Err(format!("wrote {} bytes, expected {}", written, len));
+                }
+            },
+            Action::Abort => {
+                let stream = self.stream.as_ref().ok_or("abort without accept")?;
+                stream.abort().map_err(|error| format!("abort failed: {}", error))?;
+            },
+            Action::Keepalive(keepalive) => match &self.stream {
+                Some(stream) => stream.set_keepalive(*keepalive),
+                None => self.interface.set_keepalive(*keepalive),
+            },
+            Action::State(expected) => {
+                let state = self.state();
+                if &state != expected {
+                    return Err(format!("connection is {}, expected {}", state, expected));
+                }
+            },
+            Action::Tolerance(tolerance) => self.tolerance = *tolerance,
+        }
+        Ok(())
+    }
+
+    /// Run the simulation until `until`, failing if the stack sends anything
+    fn idle(&mut self, until: Duration) -> Result<(), String> {
+        let limit = until.checked_sub(self.simulation.elapsed()).unwrap_or_default();
+        let Runner { simulation, peer, pending, .. } = self;
+        simulation.run_until(limit, || {
+            if pending.is_none() {
+                *pending = peer.recv();
+            }
+            pending.is_some()
+        });
+        match self.pending.take() {
+            Some((time, packet)) => Err(format!("unexpected {} at {:.3}", describe(&packet)?, time.as_secs_f64())),
+            None => Ok(()),
+        }
+    }
+
+    fn expect(&mut self, time: Duration, expected: &Segment) -> Result<(), String> {
+        let deadline = time + self.tolerance;
+        let limit = deadline.checked_sub(self.simulation.elapsed()).unwrap_or_default();
+        let Runner { simulation, peer, pending, .. } = self;
+        simulation.run_until(limit, || {
+            if pending.is_none() {
+                *pending = peer.recv();
+            }
+            pending.is_some()
+        });
+
+        let (arrival, packet) = self.pending.take()
+            .ok_or_else(|| format!("expected {} at {:.3}, nothing was sent", expected, time.as_secs_f64()))?;
+        let actual = decode(&packet)?;
+        if arrival + self.tolerance < time {
+            return Err(format!("expected {} at {:.3}, got {} at {:.3}", expected, time.as_secs_f64(),
+                               actual, arrival.as_secs_f64()));
+        }
+
+        let matches = actual.flags == expected.flags && actual.start == expected.start && actual.len == expected.len &&
+            expected.ack.is_none_or(|ack| actual.ack == Some(ack)) &&
+            expected.window.is_none_or(|window| actual.window == Some(window)) &&
+            expected.options.as_ref().is_none_or(|options| actual.options.as_ref() == Some(options));
+        if !matches {
+            return Err(format!("expected {}, got {}", expected, actual));
+        }
+
+        let tcph = TCPHeader::from(&packet[IPHeader::from(&packet).unwrap().size()..]).unwrap();
+        if (tcph.sourcePort, tcph.destinationPort) != (self.localPort, SCRIPT_REMOTE_PORT) {
+            return Err(format!("segment sent from port {} to {}", tcph.sourcePort, tcph.destinationPort));
+        }
+        Ok(())
+    }
+
+    /// IP packet from the peer carrying `segment` filled with `len` bytes of data
+    fn build(&self, segment: &Segment) -> Result<Vec<u8>, String> {
+        let mut tcph = TCPHeader::new(SCRIPT_REMOTE_PORT, self.localPort, segment.start, segment.window.unwrap_or(DEFAULT_WINDOW));
+        tcph.acknowledgementNumber = segment.ack.unwrap_or(0);
+        setControlBits(&mut tcph, segment.flags);
+        tcph.setOptions(segment.options.as_deref().unwrap_or(&[])).map_err(|error| error.to_string())?;
+        let tcpLen = tcph.size() + segment.len as usize;
+        let mut buf = vec![b'x'; 20 + tcpLen];
+
+        IPHeader::new(SCRIPT_REMOTE, SCRIPT_LOCAL, IPProtocol::Tcp, 64, tcpLen as u16).serialize(&mut buf);
+        tcph.serialize(&mut buf[20..]);
+        TcpSegment::newUnchecked(&mut buf[20..]).fillChecksum(SCRIPT_REMOTE, SCRIPT_LOCAL);
+        Ok(buf)
+    }
+
+    /// State of the scripted connection, `LISTEN` or `CLOSED` if there is none
+    fn state(&self) -> String {
+        let table = self.interface.connections();
+        let connection = table.connections.iter()
+            .find(|connection| connection.quad.src == (SCRIPT_REMOTE, SCRIPT_REMOTE_PORT));
+        match connection {
+            Some(connection) => stateName(connection.state),
+            None if table.listeners.iter().any(|listener| listener.addr.1 == self.localPort) => "LISTEN",
+            None => "CLOSED",
+        }.to_string()
+    }
+}
+
+/// ================================================
+///                     Parsing
+/// ================================================
+fn parseTime(token: &str, previous: Duration) -> Result<Duration, String> {
+    let (relative, seconds) = match token.strip_prefix('+') {
+        Some(seconds) => (true, seconds),
+        None => (false, token),
+    };
+    let seconds = parseSeconds(seconds)?;
+    Ok(if relative { previous + seconds } else { seconds })
+}
+
+fn parseSeconds(token: &str) -> Result<Duration, String> {
+    match token.parse::<f64>() {
+        Ok(seconds) if seconds.is_finite() && seconds >= 0.0 => Ok(Duration::from_secs_f64(seconds)),
+        _ => Err(format!("invalid time '{}'", token)),
+    }
+}
+
+fn parseNumber<T: std::str::FromStr>(token: Option<&str>, what: &str) -> Result<T, String> {
+    let token = token.ok_or_else(|| format!("missing {}", what))?;
+    token.parse().map_err(|_| format!("invalid {} '{}'", what, token))
+}
+
+fn parseAction(text: &str) -> Result<Action, String> {
+    if let Some(segment) = text.strip_prefix('<') {
+        return Ok(Action::Inject(parseSegment(segment)?));
+    }
+    if let Some(segment) = text.strip_prefix('>') {
+        return Ok(Action::Expect(parseSegment(segment)?));
+    }
+
+    let mut tokens = text.split_whitespace();
+    let command = tokens.next().ok_or("missing event")?;
+    let action = match command {
+        "bind"   => Action::Bind(parseNumber(tokens.next(), "port")?),
+        "accept" => Action::Accept,
+        "read"   => match tokens.next() {
+            Some("eof")   => Action::ReadEof,
+            Some("error") => Action::ReadError(match tokens.next() {
+                Some("reset")        => ErrorKind::ConnectionReset,
+                Some("aborted")      => ErrorKind::ConnectionAborted,
+                Some("notconnected") => ErrorKind::NotConnected,
+                Some("timedout")     => ErrorKind::TimedOut,
+                Some("wouldblock")   => ErrorKind::WouldBlock,
+                other => return Err(format!("unknown error kind {:?}", other)),
+            }),
+            length => Action::Read(parseNumber(length, "length")?),
+        },
+        "write"  => Action::Write(parseNumber(tokens.next(), "length")?),
+        "abort"  => Action::Abort,
+        "keepalive" => match tokens.next() {
+            Some("off") => Action::Keepalive(None),
+            idle => Action::Keepalive(Some(Keepalive {
+                idle: parseSeconds(idle.ok_or("missing keepalive idle time")?)?,
+                interval: parseSeconds(tokens.next().ok_or("missing keepalive interval")?)?,
+                probes: parseNumber(tokens.next(), "probe count")?,
+            })),
+        },
+        "state" => Action::State(tokens.next().ok_or("missing state")?.to_string()),
+        "tolerance" => Action::Tolerance(parseSeconds(tokens.next().ok_or("missing tolerance")?)?),
+        _ => return Err(format!("unknown event '{}'", command)),
+    };
+    if let Some(extra) = tokens.next() {
+        return Err(format!("unexpected '{}'", extra));
+    }
+    Ok(action)
+}
+
+/// `<flags> <start>:<end>(<len>) [ack <n>] [win <n>] [<options>]`
+fn parseSegment(text: &str) -> Result<Segment, String> {
+    let (fields, options) = match text.find('<') {
+        Some(open) => {
+            let close = text.rfind('>').filter(|&close| close > open).ok_or("unterminated options")?;
+            if !text[close + 1..].trim().is_empty() {
+                return Err("options must come last".to_string());
+            }
+            (&text[..open], Some(parseOptions(&text[open + 1..close])?))
+        },
+        None => (text, None),
+    };
+
+    let mut tokens = fields.split_whitespace();
+    let mut flags = 0;
+    for letter in tokens.next().ok_or("missing flags")?.chars() {
+        let (_, bit) = FLAG_LETTERS.iter().find(|(flag, _)| *flag == letter)
+            .ok_or_else(|| format!("unknown flag '{}'", letter))?;
+        flags |= bit;
+    }
+
+    // start:end(len)
+    let range = tokens.next().ok_or("missing sequence range")?;
+    let parsed = range.strip_suffix(')')
+        .and_then(|range| range.split_once('('))
+        .and_then(|(bounds, len)| bounds.split_once(':').map(|(start, end)| (start, end, len)))
+        .and_then(|(start, end, len)| Some((start.parse::<u32>().ok()?, end.parse::<u32>().ok()?, len.parse::<u32>().ok()?)));
+    let (start, end, len) = parsed.ok_or_else(|| format!("invalid sequence range '{}'", range))?;
+    if end.wrapping_sub(start) != len {
+        return Err(format!("sequence range '{}' does not match its length", range));
+    }
+
+    let mut segment = Segment { flags, start, len, ack: None, window: None, options };
+    while let Some(field) = tokens.next() {
+        match field {
+            "ack" => segment.ack = Some(parseNumber(tokens.next(), "ack")?),
+            "win" => segment.window = Some(parseNumber(tokens.next(), "window")?),
+            _ => return Err(format!("unexpected '{}'", field)),
+        }
+    }
+    Ok(segment)
+}
+
+/// Comma separated `nop`, `mss <n>`, `wscale <n>`, `sackOK`, `sack <l:r>...`, `TS val <n> ecr <n>`
+fn parseOptions(text: &str) -> Result<Vec<TcpOptionElement>, String> {
+    let mut options = Vec::new();
+    for option in text.split(',').map(str::trim).filter(|option| !option.is_empty()) {
+        let mut tokens = option.split_whitespace();
+        let parsed = match tokens.next().unwrap() {
+            "nop"    => TcpOptionElement::Nop,
+            "mss"    => TcpOptionElement::MaximumSegmentSize(parseNumber(tokens.next(), "mss")?),
+            "wscale" => TcpOptionElement::WindowScale(parseNumber(tokens.next(), "window scale")?),
+            "sackOK" => TcpOptionElement::SelectiveAcknowledgementPermitted,
+            "sack"   => {
+                let mut blocks = Vec::new();
+                for block in tokens.by_ref() {
+                    let (left, right) = block.split_once(':').ok_or_else(|| format!("invalid sack block '{}'", block))?;
+                    blocks.push((parseNumber(Some(left), "sack block")?, parseNumber(Some(right), "sack block")?));
+                }
+                if blocks.is_empty() || blocks.len() > 4 {
+                    return Err("sack needs 1 to 4 blocks".to_string());
+                }
+                let mut rest = [None; 3];
+                for (slot, block) in rest.iter_mut().zip(&blocks[1..]) {
+                    *slot = Some(*block);
+                }
+                TcpOptionElement::SelectiveAcknowledgement(blocks[0], rest)
+            },
+            "TS" => {
+                if tokens.next() != Some("val") {
+                    return Err("expected 'TS val <n> ecr <n>'".to_string());
+                }
+                let val = parseNumber(tokens.next(), "timestamp")?;
+                if tokens.next() != Some("ecr") {
+                    return Err("expected 'TS val <n> ecr <n>'".to_string());
+                }
+                TcpOptionElement::Timestamp(val, parseNumber(tokens.next(), "timestamp echo")?)
+            },
+            other => return Err(format!("unknown option '{}'", other)),
+        };
+        if let Some(extra) = tokens.next() {
+            return Err(format!("unexpected '{}' in option", extra));
+        }
+        options.push(parsed);
+    }
+    Ok(options)
+}
+
+fn optionName(option: &TcpOptionElement) -> String {
+    match option {
+        TcpOptionElement::Nop => "nop".to_string(),
+        TcpOptionElement::MaximumSegmentSize(mss) => format!("mss {}", mss),
+        TcpOptionElement::WindowScale(shift) => format!("wscale {}", shift),
+        TcpOptionElement::SelectiveAcknowledgementPermitted => "sackOK".to_string(),
+        TcpOptionElement::SelectiveAcknowledgement(first, rest) => {
+            let blocks: Vec<String> = std::iter::once(first).chain(rest.iter().flatten())
+                .map(|(left, right)| format!("{}:{}", left, right))
+                .collect();
+            format!("sack {}", blocks.join(" "))
+        },
+        TcpOptionElement::Timestamp(val, ecr) => format!("TS val {} ecr {}", val, ecr),
+    }
+}
+
+/// ================================================
+///                  Wire Format
+/// ================================================
+fn setControlBits(tcph: &mut TCPHeader, flags: u8) {
+    tcph.fin = flags & 0b0000_0001 != 0;
+    tcph.syn = flags & 0b0000_0010 != 0;
+    tcph.rst = flags & 0b0000_0100 != 0;
+    tcph.psh = flags & 0b0000_1000 != 0;
+    tcph.ack = flags & 0b0001_0000 != 0;
+    tcph.urg = flags & 0b0010_0000 != 0;
+    tcph.ece = flags & 0b0100_0000 != 0;
+    tcph.cwr = flags & 0b1000_0000 != 0;
+}
+
+/// Segment sent by the stack, checking that the packet is well formed
+fn decode(packet: &[u8]) -> Result<Segment, String> {
+    let iph = IPHeader::from(packet).map_err(|error| format!("stack sent a bad IP packet: {}", error))?;
+    let tcp = &packet[iph.size()..];
+    let tcph = TCPHeader::from(tcp).map_err(|error| format!("stack sent a bad TCP header: {}", error))?;
+    if !TCPHeader::verifyChecksum(tcp, iph.sourceIP, iph.destinationIP) {
+        return Err("stack sent a segment with a bad checksum".to_string());
+    }
+    Ok(Segment {
+        flags: tcph.getControlBits(),
+        start: tcph.sequenceNumber,
+        len: (tcp.len() - tcph.size()) as u32,
+        ack: if tcph.ack { Some(tcph.acknowledgementNumber) } else { None },
+        window: Some(tcph.window),
+        options: Some(tcph.optionElements().map_err(|error| format!("stack sent {}", error))?),
+    })
+}
+
+fn describe(packet: &[u8]) -> Result<String, String> {
+    decode(packet).map(|segment| segment.to_string())
+}
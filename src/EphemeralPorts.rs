@@ -0,0 +1,60 @@
+use crate::Parser::IPAddress;
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, Hash, Hasher};
+
+/// ===> CONSTANTS
+/// IANA suggested range for ephemeral ports
+pub(crate) const EPHEMERAL_PORT_MIN : u16 = 49152;
+pub(crate) const EPHEMERAL_PORT_MAX : u16 = 65535;
+
+/// ================================================
+///                  Port Allocator
+/// ================================================
+/// Picks ephemeral ports using "Simple Hash-Based Port Selection" (RFC 6056 Algorithm 3).
+///
+/// Search for a free port starts at an offset which is a keyed hash of the
+/// endpoints, so ports are hard to guess for an off-path attacker. A per-allocator
+/// counter makes consecutive allocations for the same endpoints move forward.
+pub(crate) struct PortAllocator {
+    /// Secret key of hash function F. Random for every allocator unless given.
+    secret: u64,
+    /// `next_ephemeral` of the RFC
+    next: u32,
+}
+
+impl PortAllocator {
+    pub(crate) fn new() -> Self {
+        PortAllocator::withSecret(RandomState::new().build_hasher().finish())
+    }
+
+    /// Allocator with a fixed key, so simulations pick the same ports on every run
+    pub(crate) fn withSecret(secret: u64) -> Self {
+        PortAllocator { secret, next: 0 }
+    }
+
+    /// Returns a port for `local` (and `remote` if known) for which `inUse` is false.
+    /// `None` when the whole ephemeral range is taken.
+    pub(crate) fn allocate<F>(&mut self, local: IPAddress, remote: Option<(IPAddress, u16)>, inUse: F) -> Option<u16>
+        where F: Fn(u16) -> bool
+    {
+        let numEphemeral = (EPHEMERAL_PORT_MAX - EPHEMERAL_PORT_MIN) as u32 + 1;
+        let offset = self.offset(local, remote);
+        for _ in 0..numEphemeral {
+            let port = EPHEMERAL_PORT_MIN as u32 + offset.wrapping_add(self.next) % numEphemeral;
+            self.next = self.next.wrapping_add(1);
+            if !inUse(port as u16) {
+                return Some(port as u16);
+            }
+        }
+        None
+    }
+
+    /// F(local, remote, secret)
+    fn offset(&self, local: IPAddress, remote: Option<(IPAddress, u16)>) -> u32 {
+        let mut hasher = DefaultHasher::new();
+        self.secret.hash(&mut hasher);
+        local.hash(&mut hasher);
+        remote.hash(&mut hasher);
+        hasher.finish() as u32
+    }
+}
@@ -0,0 +1,46 @@
+use crate::Parser::{IPOption, Ipv4Packet};
+use std::cmp::min;
+
+/// Split `packet` into fragments of at most `mtu` bytes (RFC 791 section 3.2).
+/// The first fragment keeps every option, later ones only those with the copied flag.
+/// Fragments of a fragment keep its offset and MF flag. `None` when `mtu` leaves no
+/// room for 8 bytes of payload.
+pub(crate) fn fragment(packet: &Ipv4Packet<&[u8]>, mtu: usize) -> Option<Vec<Vec<u8>>> {
+    let firstHeader = packet.header();
+    let copied: Vec<IPOption> = IPOption::parse(packet.options()).ok()?
+        .into_iter()
+        .filter(IPOption::copied)
+        .collect();
+    let mut laterHeader = [0u8; 60];
+    laterHeader[..20].copy_from_slice(&firstHeader[..20]);
+    let optionsLen = IPOption::serialize(&copied, &mut laterHeader[20..]).ok()?;
+    laterHeader[0] = 0x40 | (5 + optionsLen / 4) as u8;
+    let laterHeader = &laterHeader[..20 + optionsLen];
+
+    let payload = packet.payload();
+    let offset = packet.fragmentOffset() as usize * 8;
+    let mut fragments = Vec::new();
+    let mut at = 0;
+    while at < payload.len() {
+        let header = if at == 0 { firstHeader } else { laterHeader };
+        // Offsets are in 8 byte units, so only the last fragment may carry less
+        let room = mtu.checked_sub(header.len())? / 8 * 8;
+        if room == 0 {
+            return None;
+        }
+        let end = min(at + room, payload.len());
+
+        let mut fragment = Vec::with_capacity(header.len() + end - at);
+        fragment.extend_from_slice(header);
+        fragment.extend_from_slice(&payload[at..end]);
+        let len = fragment.len() as u16;
+        let mut view = Ipv4Packet::newUnchecked(&mut fragment[..]);
+        view.setTotalLength(len);
+        view.setFragmentOffset(((offset + at) / 8) as u16);
+        view.setMoreFragments(end < payload.len() || packet.moreFragments());
+        view.fillChecksum();
+        fragments.push(fragment);
+        at = end;
+    }
+    Some(fragments)
+}
@@ -0,0 +1,45 @@
+use crate::Parser::IPAddress;
+use crate::Random::Rng;
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU16, Ordering};
+
+/// ===> CONSTANTS
+const IDENTIFICATION_BUCKETS: usize = 256;
+
+/// ================================================
+///              IPv4 Identification
+/// ================================================
+/// Identification values of outgoing non atomic datagrams (RFC 6864).
+/// A value must not repeat for the same source, destination and protocol while
+/// fragments of the earlier datagram may still be reassembled, so every bucket of
+/// (source, destination, protocol) keeps its own counter. Buckets are picked with a
+/// keyed hash and start at random values, so an off-path host can't predict the
+/// values sent to others from the ones it receives (RFC 7739).
+/// Atomic datagrams (DF set and not a fragment) are never reassembled and get 0.
+#[derive(Debug)]
+pub(crate) struct Identifications {
+    /// Key of the bucket hash and seed of the initial counters
+    secret: u64,
+    buckets: Vec<AtomicU16>,
+}
+
+impl Identifications {
+    pub(crate) fn new() -> Self {
+        Identifications::withSecret(RandomState::new().build_hasher().finish())
+    }
+
+    /// Identifications with a fixed key, so simulations send the same values on every run
+    pub(crate) fn withSecret(secret: u64) -> Self {
+        let mut rng = Rng::new(secret);
+        let buckets = (0..IDENTIFICATION_BUCKETS).map(|_| AtomicU16::new(rng.nextU64() as u16)).collect();
+        Identifications { secret, buckets }
+    }
+
+    pub(crate) fn next(&self, source: IPAddress, destination: IPAddress, protocol: u8) -> u16 {
+        let mut hasher = DefaultHasher::new();
+        (self.secret, source, destination, protocol).hash(&mut hasher);
+        let bucket = hasher.finish() as usize % IDENTIFICATION_BUCKETS;
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,320 @@
+use crate::Random::Rng;
+use crate::VirtualNetwork::{Device, RxChecksum};
+use std::cmp::{max, Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt::{self, Debug, Formatter};
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use log::{trace, warn};
+
+/// ================================================
+///                    Impairment
+/// ================================================
+/// What happens to packets crossing an impaired link in one direction.
+/// Default is a perfect link.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Impairment {
+    /// Probability that a packet is lost, independent of other packets
+    pub loss         : f64,
+    /// Bursty loss, applied after `loss`
+    pub burstLoss    : Option<GilbertElliott>,
+    /// Delay added to every packet
+    pub latency      : Duration,
+    /// Every packet is delayed by a further uniform random amount of up to `jitter`
+    pub jitter       : Duration,
+    /// Probability that a packet is delivered twice
+    pub duplicate    : f64,
+    /// Probability that a packet is held back by `reorderDelay`, letting later packets overtake it
+    pub reorder      : f64,
+    pub reorderDelay : Duration,
+    /// Link capacity in bytes per second. Packets queue up behind each other once it is exceeded.
+    pub rate         : Option<u64>,
+}
+
+/// Two state loss model. The link moves between a good and a bad state before
+/// every packet and loses it with the probability of the state it is in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GilbertElliott {
+    pub goodToBad : f64,
+    pub badToGood : f64,
+    pub lossGood  : f64,
+    pub lossBad   : f64,
+}
+
+/// Applies an `Impairment` to a stream of packets
+#[derive(Debug)]
+pub(crate) struct Impairer {
+    pub(crate) config: Impairment,
+    rng: Rng,
+    /// Gilbert-Elliott state
+    bad: bool,
+    /// When a rate limited link finishes sending queued packets
+    nextFree: Duration,
+}
+
+impl Impairer {
+    pub(crate) fn new(config: Impairment, seed: u64) -> Self {
+        Impairer { config, rng: Rng::new(seed), bad: false, nextFree: Duration::from_secs(0) }
+    }
+
+    /// Decide the fate of a packet of `len` bytes sent at `now`.
+    /// Returns the delay of every copy to deliver, nothing if the packet is lost.
+    pub(crate) fn process(&mut self, now: Duration, len: usize) -> Vec<Duration> {
+        let config = self.config;
+        if self.chance(config.loss) {
+            return Vec::new();
+        }
+        if let Some(model) = config.burstLoss {
+            if self.chance(if self.bad { model.badToGood } else { model.goodToBad }) {
+                self.bad = !self.bad;
+            }
+            if self.chance(if self.bad { model.lossBad } else { model.lossGood }) {
+                return Vec::new();
+            }
+        }
+
+        // Wait for packets ahead of this one on a rate limited link
+        let mut departure = now;
+        if let Some(rate) = config.rate {
+            departure = max(now, self.nextFree);
+            self.nextFree = departure + Duration::from_secs_f64(len as f64 / rate.max(1) as f64);
+        }
+
+        let copies = if self.chance(config.duplicate) { 2 } else { 1 };
+        (0..copies).map(|_| {
+            let mut delay = departure - now + config.latency;
+            if config.jitter > Duration::from_secs(0) {
+                delay += config.jitter.mul_f64(self.rng.nextF64());
+            }
+            if self.chance(config.reorder) {
+                delay += config.reorderDelay;
+            }
+            delay
+        }).collect()
+    }
+
+    /// Random numbers are only drawn for enabled features, so enabling one
+    /// does not change what happens for the others with the same seed.
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.rng.nextF64() < probability
+    }
+}
+
+/// ================================================
+///                  ImpairedDevice
+/// ================================================
+/// `Device` wrapper which impairs packets received from and sent to the wrapped device.
+/// Delayed packets are held by a thread per direction, so this works with any device.
+pub struct ImpairedDevice {
+    shared: Arc<ImpairShared>,
+}
+
+struct ImpairShared {
+    start    : Instant,
+    /// MTU of the wrapped device
+    mtu      : usize,
+    inbound  : Mutex<Impairer>,
+    outbound : Mutex<Impairer>,
+    /// Received packets waiting for their delay to pass
+    received : Scheduled,
+    /// Packets to send once their delay passes
+    sending  : Scheduled,
+}
+
+/// Packets ordered by due time
+#[derive(Default)]
+struct Scheduled {
+    state : Mutex<ScheduledState>,
+    cond  : Condvar,
+}
+
+#[derive(Default)]
+struct ScheduledState {
+    queue    : BinaryHeap<Reverse<ScheduledPacket>>,
+    sequence : u64,
+    /// Set once no more packets will be added. Holds the error to report.
+    closed   : Option<io::ErrorKind>,
+    /// Device handles which may still push packets
+    handles  : usize,
+}
+
+/// Ordered by due time, then by the order packets were pushed in
+struct ScheduledPacket {
+    due      : Duration,
+    sequence : u64,
+    data     : Vec<u8>,
+    /// What the wrapped device reported about a received packet's checksums
+    checksum : RxChecksum,
+}
+
+impl PartialEq for ScheduledPacket {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ScheduledPacket {}
+
+impl PartialOrd for ScheduledPacket {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScheduledPacket {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.due, self.sequence).cmp(&(other.due, other.sequence))
+    }
+}
+
+impl Scheduled {
+    fn push(&self, due: Duration, packet: &[u8], checksum: RxChecksum) {
+        let mut state = self.state.lock().unwrap();
+        state.sequence += 1;
+        let sequence = state.sequence;
+        state.queue.push(Reverse(ScheduledPacket { due, sequence, data: packet.to_vec(), checksum }));
+        self.cond.notify_all();
+    }
+
+    fn close(&self, kind: io::ErrorKind) {
+        self.state.lock().unwrap().closed = Some(kind);
+        self.cond.notify_all();
+    }
+
+    /// Blocks until the earliest packet is due. Fails once closed and empty.
+    fn pop(&self, start: Instant) -> Result<ScheduledPacket, io::ErrorKind> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let now = start.elapsed();
+            let due = state.queue.peek().map(|Reverse(packet)| packet.due);
+            state = match due {
+                Some(due) if due <= now => {
+                    let Reverse(packet) = state.queue.pop().unwrap();
+                    return Ok(packet);
+                },
+                Some(due) => self.cond.wait_timeout(state, due - now).unwrap().0,
+                None => {
+                    if let Some(kind) = state.closed {
+                        return Err(kind);
+                    }
+                    self.cond.wait(state).unwrap()
+                }
+            };
+        }
+    }
+}
+
+impl ImpairedDevice {
+    /// Wrap `device`. Random decisions are reproducible for a given `seed`,
+    /// as long as packets arrive in the same order.
+    pub fn new<D: Device>(device: D, inbound: Impairment, outbound: Impairment, seed: u64) -> io::Result<Self> {
+        let mut rng = Rng::new(seed);
+        let shared = Arc::new(ImpairShared {
+            start: Instant::now(),
+            mtu: device.mtu(),
+            inbound: Mutex::new(Impairer::new(inbound, rng.nextU64())),
+            outbound: Mutex::new(Impairer::new(outbound, rng.nextU64())),
+            received: Scheduled::default(),
+            sending: Scheduled::default(),
+        });
+        shared.sending.state.lock().unwrap().handles = 1;
+
+        let receiver = device.try_clone()?;
+        {
+            let shared = shared.clone();
+            std::thread::spawn(move || ImpairedDevice::receiveLoop(receiver, shared));
+        }
+        {
+            let shared = shared.clone();
+            let mut sender = device;
+            std::thread::spawn(move || {
+                while let Ok(packet) = shared.sending.pop(shared.start) {
+                    if let Err(error) = sender.send(&packet.data) {
+                        warn!(error:% = error; "Impaired device failed to send");
+                    }
+                }
+            });
+        }
+        Ok(ImpairedDevice { shared })
+    }
+
+    fn receiveLoop(device: Box<dyn Device>, shared: Arc<ImpairShared>) {
+        let mut buf = [0u8; 1500];
+        loop {
+            let (len, checksum) = match device.recv_offload(&mut buf) {
+                Ok(received) => received,
+                Err(error) => {
+                    shared.received.close(error.kind());
+                    return;
+                }
+            };
+            let now = shared.start.elapsed();
+            let delays = shared.inbound.lock().unwrap().process(now, len);
+            if delays.is_empty() {
+                trace!(direction = "in", len = len; "Packet lost by impairment");
+            }
+            for delay in delays {
+                shared.received.push(now + delay, &buf[..len], checksum);
+            }
+        }
+    }
+}
+
+impl Debug for ImpairedDevice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImpairedDevice")
+            .field("inbound", &self.shared.inbound.lock().unwrap().config)
+            .field("outbound", &self.shared.outbound.lock().unwrap().config)
+            .finish()
+    }
+}
+
+impl Device for ImpairedDevice {
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.recv_offload(buf)?.0)
+    }
+
+    /// Reports what the wrapped device said about the packet, as impairment never corrupts one
+    fn recv_offload(&self, buf: &mut [u8]) -> io::Result<(usize, RxChecksum)> {
+        let packet = self.shared.received.pop(self.shared.start)?;
+        let len = packet.data.len().min(buf.len());
+        buf[..len].copy_from_slice(&packet.data[..len]);
+        Ok((len, packet.checksum))
+    }
+
+    /// Lost packets still count as sent, just like on a real link
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let now = self.shared.start.elapsed();
+        let delays = self.shared.outbound.lock().unwrap().process(now, buf.len());
+        if delays.is_empty() {
+            trace!(direction = "out", len = buf.len(); "Packet lost by impairment");
+        }
+        for delay in delays {
+            self.shared.sending.push(now + delay, buf, RxChecksum::Unverified);
+        }
+        Ok(buf.len())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Device>> {
+        self.shared.sending.state.lock().unwrap().handles += 1;
+        Ok(Box::new(ImpairedDevice { shared: self.shared.clone() }))
+    }
+
+    /// Packets are sent unchanged, so the wrapped device's limit applies
+    fn mtu(&self) -> usize {
+        self.shared.mtu
+    }
+}
+
+impl Drop for ImpairedDevice {
+    /// Sending thread stops after the last handle is gone and queued packets are sent
+    fn drop(&mut self) {
+        let mut state = self.shared.sending.state.lock().unwrap();
+        state.handles -= 1;
+        if state.handles == 0 {
+            state.closed = Some(io::ErrorKind::BrokenPipe);
+            self.shared.sending.cond.notify_all();
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{Quad, TCPState};
+use crate::Parser::IPAddress;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+/// ================================================
+///                Connection Table
+/// ================================================
+/// Snapshot of every listener and connection on an `Interface`.
+/// `Display` prints it in the same layout as `ss -tan`.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionTable {
+    pub listeners   : Vec<ListenerInfo>,
+    pub connections : Vec<ConnectionInfo>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ListenerInfo {
+    pub addr    : (IPAddress, u16),
+    /// Connections waiting to be accepted
+    pub backlog : usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// `src` is the other side, `dst` is local end
+    pub quad        : Quad,
+    pub state       : TCPState,
+    /// Bytes received but not yet read by user
+    pub recvQueue   : usize,
+    /// Bytes written by user but not yet acknowledged
+    pub sendQueue   : usize,
+    pub send        : SendSequenceInfo,
+    pub recv        : RecvSequenceInfo,
+    pub timers      : TimerInfo,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SendSequenceInfo {
+    pub una : u32,
+    pub nxt : u32,
+    pub wnd : u16,
+    pub iss : u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RecvSequenceInfo {
+    pub nxt : u32,
+    pub wnd : u16,
+    pub irs : u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimerInfo {
+    /// Time since last packet from other side
+    pub idle        : Duration,
+    /// Time until next keepalive probe, if keepalive is active
+    pub keepalive   : Option<Duration>,
+    /// Time until connection is aborted for unacknowledged data, if any
+    pub userTimeout : Option<Duration>,
+}
+
+/// State names as printed by `ss`
+pub(crate) fn stateName(state: TCPState) -> &'static str {
+    match state {
+        TCPState::Closed    => "CLOSED",
+        TCPState::Listen    => "LISTEN",
+        TCPState::SynRcvd   => "SYN-RECV",
+        TCPState::SynSnt    => "SYN-SENT",
+        TCPState::Estab     => "ESTAB",
+        TCPState::FinWait1  => "FIN-WAIT-1",
+        TCPState::FinWait2  => "FIN-WAIT-2",
+        TCPState::CloseWait => "CLOSE-WAIT",
+        TCPState::Closing   => "CLOSING",
+        TCPState::LastAck   => "LAST-ACK",
+        TCPState::TimeWait  => "TIME-WAIT",
+    }
+}
+
+impl Display for ConnectionTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<11}{:>7} {:>7} {:<26}{:<26}", "State", "Recv-Q", "Send-Q",
+                 "Local Address:Port", "Peer Address:Port")?;
+        for listener in &self.listeners {
+            let local = format!("{}:{}", listener.addr.0, listener.addr.1);
+            writeln!(f, "{:<11}{:>7} {:>7} {:<26}{:<26}", stateName(TCPState::Listen),
+                     listener.backlog, 0, local, "*:*")?;
+        }
+        for connection in &self.connections {
+            let quad = &connection.quad;
+            let local = format!("{}:{}", quad.dst.0, quad.dst.1);
+            let peer = format!("{}:{}", quad.src.0, quad.src.1);
+            writeln!(f, "{:<11}{:>7} {:>7} {:<26}{:<26}", stateName(connection.state),
+                     connection.recvQueue, connection.sendQueue, local, peer)?;
+        }
+        Ok(())
+    }
+}
@@ -1,9 +1,13 @@
 pub mod EthernetHeaderParser;
 pub mod IPHeaderParser;
+pub mod IPOptionsParser;
 pub mod TCPHeaderParser;
 pub mod IPTrafficClass;
+pub mod PacketViews;
 
 pub use EthernetHeaderParser::{MACAddress, EtherType, EthernetHeader};
 pub use IPHeaderParser::{IPVersion, IPAddress, IPHeader};
-pub use TCPHeaderParser::TCPHeader;
-pub use IPTrafficClass::IPProtocol;
\ No newline at end of file
+pub use IPOptionsParser::{IPOption, IPOptionError};
+pub use TCPHeaderParser::{TCPHeader, TCPHeaderError, TcpOptionElement};
+pub use IPTrafficClass::IPProtocol;
+pub use PacketViews::{Ipv4Packet, TcpSegment};
\ No newline at end of file
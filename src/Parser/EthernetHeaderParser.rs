@@ -1,5 +1,6 @@
 use std::fmt::{self, Display, Formatter, Result};
 use std::cmp::PartialEq;
+use crate::TCPError::{TcpError, Result as TcpResult};
 
 ///   =================================================================
 ///                          ETHERNET HEADER
@@ -8,7 +9,6 @@ use std::cmp::PartialEq;
 ///   Destination MAC Address   : 6 bytes
 ///   Source MAC Address        : 6 bytes
 ///   IPv Type                  : 2 bytes
-
 /// ============= MACAddress =============
 pub struct MACAddress{
     pub bytes: [u8; 6]
@@ -69,8 +69,10 @@ impl Display for EthernetHeader{
 }
 
 impl EthernetHeader {
-    pub fn from(buffer: &[u8]) -> Self {
-        let header: EthernetHeader;
+    pub fn from(buffer: &[u8]) -> TcpResult<Self> {
+        if buffer.len() < 14 {
+            return Err(TcpError::MalformedPacket("Ethernet header shorter than 14 bytes"));
+        }
 
         // Parser Destination Address
         let mut bytes = [0u8; 6];
@@ -90,11 +92,11 @@ impl EthernetHeader {
             _      => EtherType::Other(ipVersion)
         };
 
-        EthernetHeader {
+        Ok(EthernetHeader {
             destination,
             source,
             ipVersion
-        }
+        })
     }
     pub fn size(&self) -> usize { 14 }
 }
\ No newline at end of file
@@ -2,9 +2,10 @@ extern crate byteorder;
 use std::fmt::{self, Display, Formatter, Result};
 use std::cmp::PartialEq;
 use std::ops::BitAnd;
-use super::IPProtocol;
+use super::{IPProtocol, IPOption, IPOptionError};
 use byteorder::{ByteOrder, BigEndian, ReadBytesExt, WriteBytesExt};
 use std::num::ParseIntError;
+use crate::TCPError::{TcpError, Result as TcpResult};
 
 ///   =================================================================
 ///                             IP HEADER
@@ -44,12 +45,19 @@ impl Display for IPVersion {
     }
 }
 
-#[derive(Default, Clone, Copy, Debug, Hash, Eq, PartialEq)]
+#[derive(Default, Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct IPAddress{
     pub bytes: [u8; 4]
 }
 
 impl IPAddress {
+    /// `0.0.0.0` stands for any local address
+    pub const UNSPECIFIED: IPAddress = IPAddress { bytes: [0, 0, 0, 0] };
+
+    pub fn isUnspecified(&self) -> bool {
+        *self == IPAddress::UNSPECIFIED
+    }
+
     pub fn toString(&self) -> String {
         format!("{}.{}.{}.{}", self.bytes[0], self.bytes[1], self.bytes[2], self.bytes[3])
     }
@@ -67,6 +75,7 @@ impl Display for IPAddress {
     }
 }
 
+#[deprecated(note = "Service type now holds DSCP and ECN, use `IPHeader::dscp` and `IPHeader::ecn`")]
 pub enum IPServiceType {
     MinimizeDelay           = 0b00010000,
     MaximizeThroughput      = 0b00001000,
@@ -97,11 +106,11 @@ pub struct IPHeader{
     pub dontFragment: bool,
     pub morefragments: bool,
 
-    // IP Service Type
-    pub minimizeDelay: bool,
-    pub maximizeThroughput: bool,
-    pub maximizeReliability: bool,
-    pub minimizeMonetaryCost: bool,
+    // Service Type (RFC 2474, RFC 3168)
+    /// Differentiated Services Code Point, upper 6 bits of the service type
+    pub dscp: u8,
+    /// Explicit Congestion Notification, lower 2 bits of the service type
+    pub ecn: u8,
 }
 
 impl Default for IPHeader {
@@ -121,18 +130,16 @@ impl Default for IPHeader {
             ttl: 64,
             dontFragment: true,
             morefragments: false,
-            minimizeDelay: false,
-            maximizeThroughput: false,
-            maximizeReliability: false,
-            minimizeMonetaryCost: false
+            dscp: 0,
+            ecn: 0,
         }
     }
 }
 
 impl IPHeader {
-    pub fn from(buffer: &[u8]) -> Option<Self> {
+    pub fn from(buffer: &[u8]) -> TcpResult<Self> {
         if buffer.len() < 20 {
-            return None;
+            return Err(TcpError::MalformedPacket("IP header shorter than 20 bytes"));
         }
 
         // Parser IP Version
@@ -140,17 +147,27 @@ impl IPHeader {
         let version = match version {
             4 => IPVersion::IPv4,
             6 => IPVersion::IPv6,
-            _ => return None
+            _ => return Err(TcpError::MalformedPacket("Unknown IP version"))
         };
 
+        // Parser Header Length
+        let headerLength = buffer[0] & 0b00001111;
+        if headerLength < 5 {
+            return Err(TcpError::MalformedPacket("IP header length less than 20 bytes"));
+        }
+        if headerLength as usize * 4 > buffer.len() {
+            return Err(TcpError::MalformedPacket("IP header longer than packet"));
+        }
+        let totalLength = u16::from_be_bytes([buffer[2], buffer[3]]);
+        if (totalLength as usize) < headerLength as usize * 4 || totalLength as usize > buffer.len() {
+            return Err(TcpError::MalformedPacket("IP total length does not fit packet"));
+        }
+
         if !IPHeader::verifyChecksum(buffer) {
-            return None;
+            return Err(TcpError::ChecksumFailure);
         }
 
-        // Parser Header Length
-        let headerLength = buffer[0] & 0b00001111;
         let serviceType = buffer[1];
-        let totalLength = u16::from_be_bytes([buffer[2], buffer[3]]);
         let identification = u16::from_be_bytes([buffer[4], buffer[5]]);
         let flags = buffer[6] & 0b11100000;
         let fragmentOffset = u16::from_be_bytes([buffer[6], buffer[7]]) & 0x1FFF;
@@ -166,19 +183,20 @@ impl IPHeader {
         bytes.copy_from_slice(&buffer[16..20]);
         let destinationIP = IPAddress{ bytes };
 
-        // TODO:  Parse Options
+        let optionsLen = headerLength as usize * 4 - 20;
+        IPOption::check(&buffer[20..20 + optionsLen])?;
+        let mut options = [0u8; 40];
+        options[..optionsLen].copy_from_slice(&buffer[20..20 + optionsLen]);
 
-        Some(IPHeader {
+        Ok(IPHeader {
             version, headerLength, totalLength, identification,
             fragmentOffset, ttl, protocol, headerChecksum, sourceIP, destinationIP,
-            optionsLen: 0, options: [0; 40],
+            optionsLen: optionsLen as u8, options,
 
             dontFragment: 0 != flags & IPFlags::DontFragment as u8,
             morefragments: 0 != flags & IPFlags::MoreFragment as u8,
-            minimizeDelay: 0 != flags & IPServiceType::MinimizeDelay as u8,
-            maximizeThroughput: 0 != flags & IPServiceType::MaximizeThroughput as u8,
-            maximizeReliability: 0 != flags & IPServiceType::MaximizeReliability as u8,
-            minimizeMonetaryCost: 0 != flags & IPServiceType::MinimizeMonetaryCost as u8,
+            dscp: serviceType >> 2,
+            ecn: serviceType & 0b11,
         })
     }
 
@@ -200,10 +218,8 @@ impl IPHeader {
 
             dontFragment: true,
             morefragments: false,
-            minimizeDelay: false,
-            maximizeThroughput: false,
-            maximizeReliability: false,
-            minimizeMonetaryCost: false
+            dscp: 0,
+            ecn: 0,
         }
     }
 
@@ -214,7 +230,7 @@ impl IPHeader {
         BigEndian::write_u16(&mut buffer[2..], self.totalLength);
         BigEndian::write_u16(&mut buffer[4..], self.identification);
 
-        let value = ((self.getFlags() as u16) << 12) | self.fragmentOffset;
+        let value = ((self.getFlags() as u16) << 8) | self.fragmentOffset;
         BigEndian::write_u16(&mut buffer[6..], value);
         buffer[8] = self.ttl;
         buffer[9] = self.protocol;
@@ -222,14 +238,37 @@ impl IPHeader {
         BigEndian::write_u16(&mut buffer[10..], self.headerChecksum);
         buffer[12..16].copy_from_slice(&self.sourceIP.bytes);
         buffer[16..20].copy_from_slice(&self.destinationIP.bytes);
+        buffer[20..self.size()].copy_from_slice(&self.options[..self.optionsLen as usize]);
+    }
+
+    /// Options as typed values
+    pub fn getOptions(&self) -> std::result::Result<Vec<IPOption>, IPOptionError> {
+        IPOption::parse(&self.options[..self.optionsLen as usize])
+    }
+
+    /// Replace options, updating header length and total length to match.
+    /// A total length shorter than the header counts as no payload.
+    pub fn setOptions(&mut self, options: &[IPOption]) -> std::result::Result<(), IPOptionError> {
+        let mut buffer = [0u8; 40];
+        let optionsLen = IPOption::serialize(options, &mut buffer)?;
+        let payloadLen = (self.totalLength as usize).saturating_sub(self.size());
+        let totalLength = payloadLen + 20 + optionsLen;
+        if totalLength > u16::MAX as usize {
+            return Err(IPOptionError::PacketTooLong(totalLength));
+        }
+        self.options = buffer;
+        self.optionsLen = optionsLen as u8;
+        self.headerLength = 5 + (optionsLen / 4) as u8;
+        self.totalLength = totalLength as u16;
+        Ok(())
     }
 
     fn calcHeaderChecksum(&mut self){
-        let sum: u32 = [
+        let mut sum: u32 = [
             BigEndian::read_u16(&[(4 << 4) | self.headerLength, self.getServiceType()]),
             self.totalLength,
             self.identification,
-            ((self.getFlags() as u16) << 12) | self.fragmentOffset,
+            ((self.getFlags() as u16) << 8) | self.fragmentOffset,
             BigEndian::read_u16(&[self.ttl, self.protocol]),
             BigEndian::read_u16(&self.sourceIP.bytes[0..2]),
             BigEndian::read_u16(&self.sourceIP.bytes[2..4]),
@@ -237,17 +276,23 @@ impl IPHeader {
             BigEndian::read_u16(&self.destinationIP.bytes[2..4])
         ].iter().map(|x| u32::from(*x)).sum();
 
-        // let options = self.options();
-        // for i in 0..(options.len()/2) {
-        //     sum += u32::from( BigEndian::read_u16(&options[i*2..i*2 + 2]) );
-        // }
+        for chunk in self.options[..self.optionsLen as usize].chunks(2) {
+            sum += u32::from(BigEndian::read_u16(chunk));
+        }
 
         let carryAdd = (sum & 0xFFFF) + (sum >> 16);
         self.headerChecksum = !( ((carryAdd & 0xFFFF) + (carryAdd >> 16)) as u16 )
     }
 
+    /// False for a buffer shorter than the header length it claims
     pub fn verifyChecksum(buf: &[u8]) -> bool {
-        let headerSize: usize = ((buf[0] & 0x0F) * 4) as usize;
+        let headerSize = match buf.first() {
+            Some(first) => (first & 0x0F) as usize * 4,
+            None => return false,
+        };
+        if headerSize > buf.len() {
+            return false;
+        }
         let mut sum = 0;
         for i in (0..headerSize).step_by(2){
             sum += u16::from_be_bytes([buf[i], buf[i+1]]) as u32
@@ -266,13 +311,51 @@ impl IPHeader {
     }
 
     pub fn getServiceType(&self) -> u8 {
-        let mut value: u8 = 0;
-        if self.maximizeReliability {value |= IPServiceType::MaximizeReliability as u8;}
-        if self.maximizeThroughput {value |= IPServiceType::MaximizeThroughput as u8;}
-        if self.minimizeMonetaryCost {value |= IPServiceType::MinimizeMonetaryCost as u8;}
-        if self.minimizeDelay {value |= IPServiceType::MinimizeDelay as u8;}
-        value
+        (self.dscp << 2) | (self.ecn & 0b11)
+    }
+
+    fn setServiceType(&mut self, value: u8) {
+        self.dscp = value >> 2;
+        self.ecn = value & 0b11;
+    }
+
+    fn setServiceTypeBit(&mut self, bit: u8, value: bool) {
+        let serviceType = self.getServiceType();
+        self.setServiceType(if value { serviceType | bit } else { serviceType & !bit });
     }
 
+    #[deprecated(note = "Use `dscp`")]
+    #[allow(deprecated)]
+    pub fn minimizeDelay(&self) -> bool { 0 != self.getServiceType() & IPServiceType::MinimizeDelay as u8 }
+
+    #[deprecated(note = "Use `dscp`")]
+    #[allow(deprecated)]
+    pub fn setMinimizeDelay(&mut self, value: bool) { self.setServiceTypeBit(IPServiceType::MinimizeDelay as u8, value) }
+
+    #[deprecated(note = "Use `dscp`")]
+    #[allow(deprecated)]
+    pub fn maximizeThroughput(&self) -> bool { 0 != self.getServiceType() & IPServiceType::MaximizeThroughput as u8 }
+
+    #[deprecated(note = "Use `dscp`")]
+    #[allow(deprecated)]
+    pub fn setMaximizeThroughput(&mut self, value: bool) { self.setServiceTypeBit(IPServiceType::MaximizeThroughput as u8, value) }
+
+    #[deprecated(note = "Use `dscp`")]
+    #[allow(deprecated)]
+    pub fn maximizeReliability(&self) -> bool { 0 != self.getServiceType() & IPServiceType::MaximizeReliability as u8 }
+
+    #[deprecated(note = "Use `dscp`")]
+    #[allow(deprecated)]
+    pub fn setMaximizeReliability(&mut self, value: bool) { self.setServiceTypeBit(IPServiceType::MaximizeReliability as u8, value) }
+
+    /// The old Minimize Monetary Cost bit is the upper ECN bit now
+    #[deprecated(note = "Use `ecn`")]
+    #[allow(deprecated)]
+    pub fn minimizeMonetaryCost(&self) -> bool { 0 != self.getServiceType() & IPServiceType::MinimizeMonetaryCost as u8 }
+
+    #[deprecated(note = "Use `ecn`")]
+    #[allow(deprecated)]
+    pub fn setMinimizeMonetaryCost(&mut self, value: bool) { self.setServiceTypeBit(IPServiceType::MinimizeMonetaryCost as u8, value) }
+
     pub fn size(&self) -> usize { self.headerLength as usize * 4}
 }
\ No newline at end of file
@@ -0,0 +1,269 @@
+use super::IPAddress;
+use crate::TCPError::TcpError;
+use std::fmt::{self, Display, Formatter};
+use byteorder::{ByteOrder, BigEndian};
+
+/// ===> CONSTANTS
+pub const IP_OPTION_END_OF_LIST  : u8 = 0;
+pub const IP_OPTION_NOP          : u8 = 1;
+pub const IP_OPTION_RECORD_ROUTE : u8 = 7;
+pub const IP_OPTION_TIMESTAMP    : u8 = 68;
+pub const IP_OPTION_SECURITY     : u8 = 130;
+pub const IP_OPTION_ROUTER_ALERT : u8 = 148;
+/// Most option bytes an IPv4 header can carry
+pub const IP_OPTIONS_MAX_LEN     : usize = 40;
+
+///   =================================================================
+///                             IP OPTIONS
+///   =================================================================
+///
+///   Every option except End of Option List and No-Operation is
+///
+///   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///   |C|Cls|  Number |     Length    |     Data (Length - 2 bytes)   |
+///   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///
+///   Options take at most 40 bytes, padded with zeros to a multiple of 4.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IPOption {
+    /// Marks the end of the options, everything after it is padding
+    EndOfList,
+    Nop,
+    /// RFC 1108 basic security option
+    Security { classification: u16, compartments: u16, handling: u16, tcc: [u8; 3] },
+    /// Room for `route.len()` addresses, `pointer` (1 based, in bytes) is the next free slot
+    RecordRoute { pointer: u8, route: Vec<IPAddress> },
+    /// RFC 791 internet timestamp. `flag` 0 records timestamps only (addresses are `None`),
+    /// 1 and 3 record address and timestamp pairs.
+    Timestamp { pointer: u8, overflow: u8, flag: u8, entries: Vec<(Option<IPAddress>, u32)> },
+    /// RFC 2113, `value` 0 asks routers to examine the packet
+    RouterAlert(u16),
+    /// Any other option, kept as its raw data without kind and length
+    Unknown { kind: u8, data: Vec<u8> },
+}
+
+/// (kind, data, rest) of an option at the start of a buffer
+type RawOption<'a> = (u8, &'a [u8], &'a [u8]);
+
+/// Why a list of IP options could not be parsed or serialized
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IPOptionError {
+    /// Option of `kind` has a length that is invalid for it or runs past the end of the options
+    BadLength { kind: u8, len: usize },
+    /// Serialized options need this many bytes, more than the header has room for
+    TooLong(usize),
+    /// Buffer given to `serialize` is shorter than the padded options
+    BufferTooSmall { needed: usize, len: usize },
+    /// Packet would grow past the largest total length with the options added
+    PacketTooLong(usize),
+}
+
+impl Display for IPOptionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IPOptionError::BadLength { kind, len } => write!(f, "IP option {} has invalid length {}", kind, len),
+            IPOptionError::TooLong(len) => write!(f, "IP options of {} bytes do not fit in {} bytes", len, IP_OPTIONS_MAX_LEN),
+            IPOptionError::BufferTooSmall { needed, len } =>
+                write!(f, "IP options need {} bytes, buffer has {}", needed, len),
+            IPOptionError::PacketTooLong(len) => write!(f, "Packet of {} bytes with IP options exceeds {} bytes", len, u16::MAX),
+        }
+    }
+}
+
+impl std::error::Error for IPOptionError {}
+
+impl From<IPOptionError> for TcpError {
+    fn from(error: IPOptionError) -> Self {
+        TcpError::MalformedPacket(match error {
+            IPOptionError::BadLength {..} => "IP option length out of range",
+            IPOptionError::TooLong(_)     => "IP options longer than 40 bytes",
+            IPOptionError::BufferTooSmall {..} => "Buffer too small for IP options",
+            IPOptionError::PacketTooLong(_) => "Packet too long for IP options",
+        })
+    }
+}
+
+impl IPOption {
+    /// Parse every option in `options`, the bytes between the fixed header and the payload.
+    /// Stops after End of Option List.
+    pub fn parse(mut options: &[u8]) -> Result<Vec<IPOption>, IPOptionError> {
+        let mut parsed = Vec::new();
+        while let Some((kind, data, rest)) = IPOption::next(options)? {
+            parsed.push(IPOption::decode(kind, data));
+            if kind == IP_OPTION_END_OF_LIST { break; }
+            options = rest;
+        }
+        Ok(parsed)
+    }
+
+    /// Same checks as `parse` without building the options
+    pub fn check(mut options: &[u8]) -> Result<(), IPOptionError> {
+        while let Some((kind, _, rest)) = IPOption::next(options)? {
+            if kind == IP_OPTION_END_OF_LIST { break; }
+            options = rest;
+        }
+        Ok(())
+    }
+
+    /// First option, with the length checked for the kind
+    fn next(options: &[u8]) -> Result<Option<RawOption<'_>>, IPOptionError> {
+        let kind = match options.first() {
+            Some(&kind) => kind,
+            None => return Ok(None),
+        };
+        if kind == IP_OPTION_END_OF_LIST || kind == IP_OPTION_NOP {
+            return Ok(Some((kind, &[], &options[1..])));
+        }
+
+        let len = options.get(1).map_or(0, |&len| len as usize);
+        let valid = len >= 2 && len <= options.len() && match kind {
+            IP_OPTION_SECURITY     => len == 11,
+            IP_OPTION_RECORD_ROUTE => len >= 3 && (len - 3).is_multiple_of(4) && options[2] >= 4,
+            IP_OPTION_TIMESTAMP    => len >= 4 && options[2] >= 5 && (len - 4).is_multiple_of(IPOption::timestampEntrySize(options[3])),
+            IP_OPTION_ROUTER_ALERT => len == 4,
+            _ => true,
+        };
+        if !valid {
+            return Err(IPOptionError::BadLength { kind, len });
+        }
+        Ok(Some((kind, &options[2..len], &options[len..])))
+    }
+
+    fn timestampEntrySize(overflowFlag: u8) -> usize {
+        if overflowFlag & 0x0F == 0 { 4 } else { 8 }
+    }
+
+    /// `data` has passed the length checks of `next`
+    fn decode(kind: u8, data: &[u8]) -> IPOption {
+        let address = |bytes: &[u8]| IPAddress::new(bytes[0], bytes[1], bytes[2], bytes[3]);
+        match kind {
+            IP_OPTION_END_OF_LIST => IPOption::EndOfList,
+            IP_OPTION_NOP => IPOption::Nop,
+            IP_OPTION_SECURITY => IPOption::Security {
+                classification: BigEndian::read_u16(&data[0..2]),
+                compartments: BigEndian::read_u16(&data[2..4]),
+                handling: BigEndian::read_u16(&data[4..6]),
+                tcc: [data[6], data[7], data[8]],
+            },
+            IP_OPTION_RECORD_ROUTE => IPOption::RecordRoute {
+                pointer: data[0],
+                route: data[1..].chunks(4).map(address).collect(),
+            },
+            IP_OPTION_TIMESTAMP => {
+                let flag = data[1] & 0x0F;
+                let entries = data[2..].chunks(IPOption::timestampEntrySize(flag))
+                    .map(|entry| match entry.len() {
+                        8 => (Some(address(&entry[..4])), BigEndian::read_u32(&entry[4..])),
+                        _ => (None, BigEndian::read_u32(entry)),
+                    })
+                    .collect();
+                IPOption::Timestamp { pointer: data[0], overflow: data[1] >> 4, flag, entries }
+            },
+            IP_OPTION_ROUTER_ALERT => IPOption::RouterAlert(BigEndian::read_u16(data)),
+            _ => IPOption::Unknown { kind, data: data.to_vec() },
+        }
+    }
+
+    pub fn kind(&self) -> u8 {
+        match self {
+            IPOption::EndOfList           => IP_OPTION_END_OF_LIST,
+            IPOption::Nop                 => IP_OPTION_NOP,
+            IPOption::Security {..}       => IP_OPTION_SECURITY,
+            IPOption::RecordRoute {..}    => IP_OPTION_RECORD_ROUTE,
+            IPOption::Timestamp {..}      => IP_OPTION_TIMESTAMP,
+            IPOption::RouterAlert(_)      => IP_OPTION_ROUTER_ALERT,
+            IPOption::Unknown { kind, ..} => *kind,
+        }
+    }
+
+    /// Copied flag: the option goes into every fragment, not only the first
+    pub fn copied(&self) -> bool {
+        self.kind() & 0x80 != 0
+    }
+
+    /// Fields that don't fit their bits on the wire, or that `parse` would not give back
+    fn checkFields(&self) -> Result<(), IPOptionError> {
+        let valid = match self {
+            // Overflow and flag share a byte, and flag 0 entries have no address
+            IPOption::Timestamp { overflow, flag, entries, .. } =>
+                *overflow <= 0x0F && *flag <= 0x0F && (*flag != 0 || entries.iter().all(|(address, _)| address.is_none())),
+            _ => true,
+        };
+        if !valid {
+            return Err(IPOptionError::BadLength { kind: self.kind(), len: self.size() });
+        }
+        Ok(())
+    }
+
+    /// Bytes taken by this option, including kind and length
+    pub fn size(&self) -> usize {
+        match self {
+            IPOption::EndOfList | IPOption::Nop   => 1,
+            IPOption::Security {..}               => 11,
+            IPOption::RecordRoute { route, .. }   => 3 + 4 * route.len(),
+            IPOption::Timestamp { flag, entries, .. } => 4 + IPOption::timestampEntrySize(*flag) * entries.len(),
+            IPOption::RouterAlert(_)              => 4,
+            IPOption::Unknown { data, .. }        => 2 + data.len(),
+        }
+    }
+
+    /// Write `options` followed by zero padding to a multiple of 4 bytes.
+    /// Returns the padded length. Nothing is written if an option can't be
+    /// represented on the wire or `buffer` is too small.
+    pub fn serialize(options: &[IPOption], buffer: &mut [u8]) -> Result<usize, IPOptionError> {
+        let len: usize = options.iter().map(IPOption::size).sum();
+        let padded = len.div_ceil(4) * 4;
+        if padded > IP_OPTIONS_MAX_LEN {
+            return Err(IPOptionError::TooLong(len));
+        }
+        if buffer.len() < padded {
+            return Err(IPOptionError::BufferTooSmall { needed: padded, len: buffer.len() });
+        }
+        for option in options {
+            option.checkFields()?;
+        }
+
+        let mut at = 0;
+        for option in options {
+            let size = option.size();
+            let out = &mut buffer[at..at + size];
+            out[0] = option.kind();
+            if size > 1 {
+                out[1] = size as u8;
+            }
+            match option {
+                IPOption::EndOfList | IPOption::Nop => {},
+                IPOption::Security { classification, compartments, handling, tcc } => {
+                    BigEndian::write_u16(&mut out[2..], *classification);
+                    BigEndian::write_u16(&mut out[4..], *compartments);
+                    BigEndian::write_u16(&mut out[6..], *handling);
+                    out[8..11].copy_from_slice(tcc);
+                },
+                IPOption::RecordRoute { pointer, route } => {
+                    out[2] = *pointer;
+                    for (slot, address) in out[3..].chunks_mut(4).zip(route) {
+                        slot.copy_from_slice(&address.bytes);
+                    }
+                },
+                IPOption::Timestamp { pointer, overflow, flag, entries } => {
+                    out[2] = *pointer;
+                    out[3] = (overflow << 4) | flag;
+                    for (slot, (address, timestamp)) in out[4..].chunks_mut(IPOption::timestampEntrySize(*flag)).zip(entries) {
+                        let at = slot.len() - 4;
+                        if at == 4 {
+                            slot[..4].copy_from_slice(&address.unwrap_or_default().bytes);
+                        }
+                        BigEndian::write_u32(&mut slot[at..], *timestamp);
+                    }
+                },
+                IPOption::RouterAlert(value) => BigEndian::write_u16(&mut out[2..], *value),
+                IPOption::Unknown { data, .. } => out[2..].copy_from_slice(data),
+            }
+            at += size;
+        }
+        for byte in &mut buffer[at..padded] {
+            *byte = 0;
+        }
+        Ok(padded)
+    }
+}
@@ -0,0 +1,273 @@
+use super::{IPAddress, IPHeader, IPOption, TCPHeader, TCPHeaderError};
+use super::TCPHeaderParser::flagLetters;
+use crate::TCPError::{TcpError, Result as TcpResult};
+use byteorder::{ByteOrder, BigEndian};
+
+/// ================================================
+///                   Packet Views
+/// ================================================
+/// `Ipv4Packet` and `TcpSegment` read and write header fields in place,
+/// over any buffer (`&[u8]`, `&mut [u8]`, `Vec<u8>`). Unlike `IPHeader`
+/// and `TCPHeader` nothing is copied out, so the receive path can
+/// dispatch a packet straight from the device buffer.
+///
+/// `new` does the same length checks as the header parsers, after which
+/// every accessor is in bounds. Setters never change the header length.
+///
+/// `Ipv4Packet` wraps a buffer starting at the IP header.
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv4Packet<T> {
+    buffer: T,
+}
+
+impl<T: AsRef<[u8]>> Ipv4Packet<T> {
+    /// Checks that the header and total length fit in `buffer` and options are well formed.
+    /// Header checksum is left to `verifyChecksum`.
+    pub fn new(buffer: T) -> TcpResult<Self> {
+        let buf = buffer.as_ref();
+        if buf.len() < 20 {
+            return Err(TcpError::MalformedPacket("IP header shorter than 20 bytes"));
+        }
+        if buf[0] >> 4 != 4 {
+            return Err(TcpError::MalformedPacket("Unknown IP version"));
+        }
+        let headerSize = (buf[0] & 0x0F) as usize * 4;
+        if headerSize < 20 {
+            return Err(TcpError::MalformedPacket("IP header length less than 20 bytes"));
+        }
+        if headerSize > buf.len() {
+            return Err(TcpError::MalformedPacket("IP header longer than packet"));
+        }
+        let totalLength = BigEndian::read_u16(&buf[2..4]) as usize;
+        if totalLength < headerSize || totalLength > buf.len() {
+            return Err(TcpError::MalformedPacket("IP total length does not fit packet"));
+        }
+        IPOption::check(&buf[20..headerSize])?;
+        Ok(Ipv4Packet { buffer })
+    }
+
+    /// Wraps `buffer` without any checks. Accessors panic if it is too short.
+    pub fn newUnchecked(buffer: T) -> Self {
+        Ipv4Packet { buffer }
+    }
+
+    pub fn intoInner(self) -> T {
+        self.buffer
+    }
+
+    fn buf(&self) -> &[u8] {
+        self.buffer.as_ref()
+    }
+
+    pub fn version(&self) -> u8 { self.buf()[0] >> 4 }
+
+    /// Header length in 32 bit words
+    pub fn headerLength(&self) -> u8 { self.buf()[0] & 0x0F }
+
+    /// Header length in bytes, including options
+    pub fn size(&self) -> usize { self.headerLength() as usize * 4 }
+
+    pub fn serviceType(&self) -> u8 { self.buf()[1] }
+
+    pub fn totalLength(&self) -> u16 { BigEndian::read_u16(&self.buf()[2..4]) }
+
+    pub fn identification(&self) -> u16 { BigEndian::read_u16(&self.buf()[4..6]) }
+
+    pub fn dontFragment(&self) -> bool { self.buf()[6] & 0b01000000 != 0 }
+
+    pub fn moreFragments(&self) -> bool { self.buf()[6] & 0b00100000 != 0 }
+
+    /// Offset of this fragment in 8 byte units
+    pub fn fragmentOffset(&self) -> u16 { BigEndian::read_u16(&self.buf()[6..8]) & 0x1FFF }
+
+    pub fn ttl(&self) -> u8 { self.buf()[8] }
+
+    pub fn protocol(&self) -> u8 { self.buf()[9] }
+
+    pub fn headerChecksum(&self) -> u16 { BigEndian::read_u16(&self.buf()[10..12]) }
+
+    pub fn sourceIP(&self) -> IPAddress { Self::address(&self.buf()[12..16]) }
+
+    pub fn destinationIP(&self) -> IPAddress { Self::address(&self.buf()[16..20]) }
+
+    fn address(bytes: &[u8]) -> IPAddress {
+        IPAddress::new(bytes[0], bytes[1], bytes[2], bytes[3])
+    }
+
+    pub fn options(&self) -> &[u8] { &self.buf()[20..self.size()] }
+
+    /// Fixed header and options
+    pub fn header(&self) -> &[u8] { &self.buf()[..self.size()] }
+
+    /// Bytes between the header and total length. Link layer padding is left out.
+    pub fn payload(&self) -> &[u8] { &self.buf()[self.size()..self.totalLength() as usize] }
+
+    pub fn verifyChecksum(&self) -> bool { IPHeader::verifyChecksum(self.buf()) }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Ipv4Packet<T> {
+    fn bufMut(&mut self) -> &mut [u8] {
+        self.buffer.as_mut()
+    }
+
+    pub fn setServiceType(&mut self, serviceType: u8) { self.bufMut()[1] = serviceType; }
+
+    pub fn setTotalLength(&mut self, totalLength: u16) { BigEndian::write_u16(&mut self.bufMut()[2..4], totalLength); }
+
+    pub fn setIdentification(&mut self, identification: u16) { BigEndian::write_u16(&mut self.bufMut()[4..6], identification); }
+
+    pub fn setDontFragment(&mut self, value: bool) { self.setFlag(0b01000000, value); }
+
+    pub fn setMoreFragments(&mut self, value: bool) { self.setFlag(0b00100000, value); }
+
+    fn setFlag(&mut self, flag: u8, value: bool) {
+        if value { self.bufMut()[6] |= flag; } else { self.bufMut()[6] &= !flag; }
+    }
+
+    /// Offset in 8 byte units, flags are kept
+    pub fn setFragmentOffset(&mut self, offset: u16) {
+        let flags = self.buf()[6] & 0b11100000;
+        BigEndian::write_u16(&mut self.bufMut()[6..8], offset & 0x1FFF);
+        self.bufMut()[6] |= flags;
+    }
+
+    pub fn setTtl(&mut self, ttl: u8) { self.bufMut()[8] = ttl; }
+
+    pub fn setProtocol(&mut self, protocol: u8) { self.bufMut()[9] = protocol; }
+
+    pub fn setSourceIP(&mut self, address: IPAddress) { self.bufMut()[12..16].copy_from_slice(&address.bytes); }
+
+    pub fn setDestinationIP(&mut self, address: IPAddress) { self.bufMut()[16..20].copy_from_slice(&address.bytes); }
+
+    /// Recompute header checksum after changing any field
+    pub fn fillChecksum(&mut self) {
+        let size = self.size();
+        let buf = self.bufMut();
+        buf[10..12].copy_from_slice(&[0, 0]);
+        let mut sum: u32 = buf[..size].chunks(2).map(|chunk| u32::from(BigEndian::read_u16(chunk))).sum();
+        while sum >> 16 != 0 {
+            sum = (sum & 0xFFFF) + (sum >> 16);
+        }
+        BigEndian::write_u16(&mut buf[10..12], !(sum as u16));
+    }
+
+    pub fn payloadMut(&mut self) -> &mut [u8] {
+        let (start, end) = (self.size(), self.totalLength() as usize);
+        &mut self.bufMut()[start..end]
+    }
+}
+
+/// TCP segment over `buffer`, which holds exactly the header, options and payload
+#[derive(Debug, Clone, Copy)]
+pub struct TcpSegment<T> {
+    buffer: T,
+}
+
+impl<T: AsRef<[u8]>> TcpSegment<T> {
+    /// Same checks as `TCPHeader::from`
+    pub fn new(buffer: T) -> Result<Self, TCPHeaderError> {
+        let buf = buffer.as_ref();
+        if buf.len() < 20 {
+            return Err(TCPHeaderError::TooShort(buf.len()));
+        }
+        let headerLength = buf[12] >> 4;
+        if headerLength < 5 {
+            return Err(TCPHeaderError::DataOffsetTooSmall(headerLength));
+        }
+        let headerSize = headerLength as usize * 4;
+        if headerSize > buf.len() {
+            return Err(TCPHeaderError::DataOffsetTooLarge { dataOffset: headerLength, len: buf.len() });
+        }
+        TCPHeader::checkOptions(&buf[20..headerSize])?;
+        Ok(TcpSegment { buffer })
+    }
+
+    /// Wraps `buffer` without any checks. Accessors panic if it is too short.
+    pub fn newUnchecked(buffer: T) -> Self {
+        TcpSegment { buffer }
+    }
+
+    pub fn intoInner(self) -> T {
+        self.buffer
+    }
+
+    fn buf(&self) -> &[u8] {
+        self.buffer.as_ref()
+    }
+
+    pub fn sourcePort(&self) -> u16 { BigEndian::read_u16(&self.buf()[0..2]) }
+
+    pub fn destinationPort(&self) -> u16 { BigEndian::read_u16(&self.buf()[2..4]) }
+
+    pub fn sequenceNumber(&self) -> u32 { BigEndian::read_u32(&self.buf()[4..8]) }
+
+    pub fn acknowledgementNumber(&self) -> u32 { BigEndian::read_u32(&self.buf()[8..12]) }
+
+    /// Data offset in 32 bit words
+    pub fn headerLength(&self) -> u8 { self.buf()[12] >> 4 }
+
+    /// Header length in bytes, including options
+    pub fn size(&self) -> usize { self.headerLength() as usize * 4 }
+
+    /// Same layout as `TCPHeader::getControlBits`
+    pub fn controlBits(&self) -> u8 { self.buf()[13] }
+
+    pub fn fin(&self) -> bool { self.controlBits() & 0b00000001 != 0 }
+    pub fn syn(&self) -> bool { self.controlBits() & 0b00000010 != 0 }
+    pub fn rst(&self) -> bool { self.controlBits() & 0b00000100 != 0 }
+    pub fn psh(&self) -> bool { self.controlBits() & 0b00001000 != 0 }
+    pub fn ack(&self) -> bool { self.controlBits() & 0b00010000 != 0 }
+    pub fn urg(&self) -> bool { self.controlBits() & 0b00100000 != 0 }
+    pub fn ece(&self) -> bool { self.controlBits() & 0b01000000 != 0 }
+    pub fn cwr(&self) -> bool { self.controlBits() & 0b10000000 != 0 }
+
+    /// Set control bits as letters, same as `TCPHeader::flags`
+    pub fn flags(&self) -> String { flagLetters(self.controlBits()) }
+
+    pub fn window(&self) -> u16 { BigEndian::read_u16(&self.buf()[14..16]) }
+
+    pub fn checksum(&self) -> u16 { BigEndian::read_u16(&self.buf()[16..18]) }
+
+    pub fn urgentPointer(&self) -> u16 { BigEndian::read_u16(&self.buf()[18..20]) }
+
+    pub fn options(&self) -> &[u8] { &self.buf()[20..self.size()] }
+
+    pub fn payload(&self) -> &[u8] { &self.buf()[self.size()..] }
+
+    pub fn verifyChecksum(&self, sourceIP: IPAddress, destinationIP: IPAddress) -> bool {
+        TCPHeader::verifyChecksum(self.buf(), sourceIP, destinationIP)
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> TcpSegment<T> {
+    fn bufMut(&mut self) -> &mut [u8] {
+        self.buffer.as_mut()
+    }
+
+    pub fn setSourcePort(&mut self, port: u16) { BigEndian::write_u16(&mut self.bufMut()[0..2], port); }
+
+    pub fn setDestinationPort(&mut self, port: u16) { BigEndian::write_u16(&mut self.bufMut()[2..4], port); }
+
+    pub fn setSequenceNumber(&mut self, seq: u32) { BigEndian::write_u32(&mut self.bufMut()[4..8], seq); }
+
+    pub fn setAcknowledgementNumber(&mut self, ack: u32) { BigEndian::write_u32(&mut self.bufMut()[8..12], ack); }
+
+    pub fn setControlBits(&mut self, controlBits: u8) { self.bufMut()[13] = controlBits; }
+
+    pub fn setWindow(&mut self, window: u16) { BigEndian::write_u16(&mut self.bufMut()[14..16], window); }
+
+    pub fn setUrgentPointer(&mut self, urgentPointer: u16) { BigEndian::write_u16(&mut self.bufMut()[18..20], urgentPointer); }
+
+    /// Recompute checksum over the whole segment after changing any field or the payload
+    pub fn fillChecksum(&mut self, sourceIP: IPAddress, destinationIP: IPAddress) {
+        let buf = self.bufMut();
+        buf[16..18].copy_from_slice(&[0, 0]);
+        let sum = TCPHeader::segmentSum(buf, sourceIP, destinationIP);
+        BigEndian::write_u16(&mut buf[16..18], !sum);
+    }
+
+    pub fn payloadMut(&mut self) -> &mut [u8] {
+        let start = self.size();
+        &mut self.bufMut()[start..]
+    }
+}
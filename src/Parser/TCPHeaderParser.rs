@@ -1,5 +1,7 @@
 extern crate byteorder;
 use crate::Parser::IPAddress;
+use crate::TCPError::TcpError;
+use std::fmt::{self, Display, Formatter};
 use self::byteorder::{ByteOrder, BigEndian, ReadBytesExt, WriteBytesExt};
 
 ///   =================================================================
@@ -25,7 +27,6 @@ use self::byteorder::{ByteOrder, BigEndian, ReadBytesExt, WriteBytesExt};
 ///   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 ///   |                             data                              |
 ///   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-
 pub struct TCPHeader{
     pub sourcePort              :  u16,
     pub destinationPort         :  u16,
@@ -49,8 +50,61 @@ pub struct TCPHeader{
     pub cwr: bool,
 }
 
+/// Why `TCPHeader::from` rejected a segment
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TCPHeaderError {
+    /// Segment is shorter than the 20 byte fixed header
+    TooShort(usize),
+    /// Data offset (in 32 bit words) is below the minimum of 5
+    DataOffsetTooSmall(u8),
+    /// Data offset points past the end of the segment
+    DataOffsetTooLarge { dataOffset: u8, len: usize },
+    /// Option of `kind` has a length below 2 or runs past the end of the header
+    BadOptionLength { kind: u8, len: usize },
+    /// Option of `kind` is not one of `TcpOptionElement`, or has the wrong length for its kind
+    UnknownOption { kind: u8, len: usize },
+    /// Options need this many bytes, more than the 40 a header can carry
+    OptionsTooLong(usize),
+}
+
+impl Display for TCPHeaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TCPHeaderError::TooShort(len) => write!(f, "TCP segment of {} bytes is shorter than header", len),
+            TCPHeaderError::DataOffsetTooSmall(dataOffset) => write!(f, "TCP data offset {} is less than 5", dataOffset),
+            TCPHeaderError::DataOffsetTooLarge { dataOffset, len } =>
+                write!(f, "TCP data offset {} is past the end of {} byte segment", dataOffset, len),
+            TCPHeaderError::BadOptionLength { kind, len } => write!(f, "TCP option {} has invalid length {}", kind, len),
+            TCPHeaderError::UnknownOption { kind, len } => write!(f, "unknown or malformed TCP option {} of length {}", kind, len),
+            TCPHeaderError::OptionsTooLong(len) => write!(f, "TCP options of {} bytes do not fit in 40", len),
+        }
+    }
+}
+
+impl std::error::Error for TCPHeaderError {}
+
+impl From<TCPHeaderError> for TcpError {
+    fn from(error: TCPHeaderError) -> Self {
+        TcpError::MalformedPacket(match error {
+            TCPHeaderError::TooShort(_)             => "TCP header shorter than 20 bytes",
+            TCPHeaderError::DataOffsetTooSmall(_)   => "TCP data offset less than 5",
+            TCPHeaderError::DataOffsetTooLarge {..} => "TCP data offset past end of segment",
+            TCPHeaderError::BadOptionLength {..}    => "TCP option length out of range",
+            TCPHeaderError::UnknownOption {..}      => "Unknown or malformed TCP option",
+            TCPHeaderError::OptionsTooLong(_)       => "TCP options longer than 40 bytes",
+        })
+    }
+}
+
 impl TCPHeader {
-    pub fn from(buffer: &[u8]) -> Self{
+    /// Parse the header at the start of `buffer`, which holds the whole segment.
+    /// Succeeds only if the header and its options fit in `buffer`,
+    /// so `buffer[header.size()..]` is always the payload.
+    pub fn from(buffer: &[u8]) -> Result<Self, TCPHeaderError> {
+        if buffer.len() < 20 {
+            return Err(TCPHeaderError::TooShort(buffer.len()));
+        }
+
         let sourcePort = u16::from_be_bytes([buffer[0], buffer[1]]);
         let destinationPort = u16::from_be_bytes([buffer[2], buffer[3]]);
         let sequenceNumber = u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]);
@@ -65,14 +119,21 @@ impl TCPHeader {
         let checksum = u16::from_be_bytes([buffer[16], buffer[17]]);
         let urgentPointer = u16::from_be_bytes([buffer[18], buffer[19]]);
 
-        // TODO: Parser option correctly
-        // let mut optionsBuff = [0u8; 8];
-        // optionsBuff.clone_from_slice(&buffer[20..headerLength]);
+        if headerLength < 5 {
+            return Err(TCPHeaderError::DataOffsetTooSmall(headerLength));
+        }
+        let headerSize = headerLength as usize * 4;
+        if headerSize > buffer.len() {
+            return Err(TCPHeaderError::DataOffsetTooLarge { dataOffset: headerLength, len: buffer.len() });
+        }
+        TCPHeader::checkOptions(&buffer[20..headerSize])?;
+        let mut options = [0u8; 40];
+        options[..headerSize - 20].copy_from_slice(&buffer[20..headerSize]);
 
-        TCPHeader {
+        Ok(TCPHeader {
             sourcePort, destinationPort, sequenceNumber, acknowledgementNumber,
             headerLength, reserved, window, checksum, urgentPointer,
-            options: [0; 40],
+            options,
             fin: 0 != controlBits & 0b00000001,
             syn: 0 != controlBits & 0b00000010,
             rst: 0 != controlBits & 0b00000100,
@@ -81,7 +142,26 @@ impl TCPHeader {
             urg: 0 != controlBits & 0b00100000,
             ece: 0 != controlBits & 0b01000000,
             cwr: 0 != controlBits & 0b10000000,
+        })
+    }
+
+    /// Every option except End of Option List and No-Operation has a
+    /// length byte covering itself and the kind, which must stay within `options`
+    pub(crate) fn checkOptions(mut options: &[u8]) -> Result<(), TCPHeaderError> {
+        while let Some(&kind) = options.first() {
+            match kind {
+                0 => return Ok(()),
+                1 => options = &options[1..],
+                _ => {
+                    let len = options.get(1).map_or(0, |&len| len as usize);
+                    if len < 2 || len > options.len() {
+                        return Err(TCPHeaderError::BadOptionLength { kind, len });
+                    }
+                    options = &options[len..];
+                }
+            }
         }
+        Ok(())
     }
 
     pub fn new(sourcePort: u16, destinationPort: u16, sequenceNumber: u32, window: u16) -> Self {
@@ -92,7 +172,7 @@ impl TCPHeader {
             acknowledgementNumber: 0,
             headerLength: 5,
             reserved: 0,
-            window: window,
+            window,
             checksum: 0,
             urgentPointer: 0,
             options: [0; 40],
@@ -128,6 +208,7 @@ impl TCPHeader {
         BigEndian::write_u16(&mut buffer[14..], self.window);
         BigEndian::write_u16(&mut buffer[16..], self.checksum);
         BigEndian::write_u16(&mut buffer[18..], self.urgentPointer);
+        buffer[20..self.size()].copy_from_slice(&self.options[..self.size() - 20]);
     }
 
     pub fn getControlBits(&self) -> u8{
@@ -143,6 +224,11 @@ impl TCPHeader {
         controlBits
     }
 
+    /// Set control bits as letters, e.g. "SA" for a syn-ack. Used in logs.
+    pub fn flags(&self) -> String {
+        flagLetters(self.getControlBits())
+    }
+
     pub fn calcChecksum(&mut self, sourceIP: IPAddress, destinationIP: IPAddress, payload: &[u8]) {
         // Check Length Constraint
         let tcpLength = (self.headerLength * 4) as usize + payload.len();
@@ -179,11 +265,10 @@ impl TCPHeader {
             u64::from(self.window) +
             u64::from(self.urgentPointer);
 
-        //add the options
-        // let options_len = self.options_len();
-        // for i in RangeStep::new(0, options_len, 2) {
-        //     sum += u64::from( BigEndian::read_u16(&self.options_buffer[i..i + 2]) );
-        // }
+        // Options
+        for i in (0..self.size() - 20).step_by(2) {
+            sum += u64::from(BigEndian::read_u16(&self.options[i..i + 2]));
+        }
 
         // Payload
         let n = (payload.len() / 2) * 2;
@@ -204,24 +289,107 @@ impl TCPHeader {
         !result
     }
 
+    /// Checks the checksum of a whole segment (header, options and payload)
+    /// together with the pseudo header built from the IP addresses
     pub fn verifyChecksum(buf: &[u8], sourceIP: IPAddress, destinationIP: IPAddress) -> bool {
-        let headerSize: usize = ((buf[13] & 0x0F) * 4) as usize;
-        let mut sum = 0;
-        for i in (0..headerSize).step_by(2){
-            sum += u16::from_be_bytes([buf[i], buf[i+1]]) as u32
+        TCPHeader::segmentSum(buf, sourceIP, destinationIP) == 0xFFFF
+    }
+
+    /// One's complement sum of the pseudo header and `buf`, folded to 16 bits
+    pub(crate) fn segmentSum(buf: &[u8], sourceIP: IPAddress, destinationIP: IPAddress) -> u16 {
+        let mut sum: u64 = 6 + buf.len() as u64;
+        for address in [sourceIP, destinationIP].iter() {
+            sum += u64::from(u16::from_be_bytes([address.bytes[0], address.bytes[1]]));
+            sum += u64::from(u16::from_be_bytes([address.bytes[2], address.bytes[3]]));
         }
+        for chunk in buf.chunks(2) {
+            sum += u64::from(u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)]));
+        }
+        while sum >> 16 != 0 {
+            sum = (sum & 0xFFFF) + (sum >> 16);
+        }
+        sum as u16
+    }
+
+    pub fn size(&self) -> usize {self.headerLength as usize * 4}
 
-        sum += u16::from_be_bytes([sourceIP.bytes[0], sourceIP.bytes[1]]) as u32;
-        sum += u16::from_be_bytes([sourceIP.bytes[2], sourceIP.bytes[3]]) as u32;
-        sum += u16::from_be_bytes([destinationIP.bytes[0], destinationIP.bytes[1]]) as u32;
-        sum += u16::from_be_bytes([destinationIP.bytes[2], destinationIP.bytes[3]]) as u32;
+    /// Replace the options, padded with End of Option List to a multiple of 4 bytes,
+    /// and set `headerLength` to cover them
+    pub fn setOptions(&mut self, options: &[TcpOptionElement]) -> Result<(), TCPHeaderError> {
+        let mut bytes = Vec::new();
+        for option in options {
+            match option {
+                TcpOptionElement::Nop => bytes.push(TCP_OPTION_ID_NOP),
+                TcpOptionElement::MaximumSegmentSize(mss) => {
+                    bytes.extend_from_slice(&[TCP_OPTION_ID_MAXIMUM_SEGMENT_SIZE, 4]);
+                    bytes.extend_from_slice(&mss.to_be_bytes());
+                },
+                TcpOptionElement::WindowScale(shift) => bytes.extend_from_slice(&[TCP_OPTION_ID_WINDOW_SCALE, 3, *shift]),
+                TcpOptionElement::SelectiveAcknowledgementPermitted => bytes.extend_from_slice(&[TCP_OPTION_ID_SELECTIVE_ACK_PERMITTED, 2]),
+                TcpOptionElement::SelectiveAcknowledgement(first, rest) => {
+                    let blocks: Vec<&(u32, u32)> = std::iter::once(first).chain(rest.iter().flatten()).collect();
+                    bytes.extend_from_slice(&[TCP_OPTION_ID_SELECTIVE_ACK, 2 + 8 * blocks.len() as u8]);
+                    for (left, right) in blocks {
+                        bytes.extend_from_slice(&left.to_be_bytes());
+                        bytes.extend_from_slice(&right.to_be_bytes());
+                    }
+                },
+                TcpOptionElement::Timestamp(val, ecr) => {
+                    bytes.extend_from_slice(&[TCP_OPTION_ID_TIMESTAMP, 10]);
+                    bytes.extend_from_slice(&val.to_be_bytes());
+                    bytes.extend_from_slice(&ecr.to_be_bytes());
+                },
+            }
+        }
+        if bytes.len() > 40 {
+            return Err(TCPHeaderError::OptionsTooLong(bytes.len()));
+        }
 
-        sum = (sum >> 16) + (sum & 0x0000FFFF);
-        sum = (sum >> 16) + (sum & 0x0000FFFF);
-        sum + 1 == (1 << 16)
+        self.options = [0; 40];
+        self.options[..bytes.len()].copy_from_slice(&bytes);
+        self.headerLength = 5 + bytes.len().div_ceil(4) as u8;
+        Ok(())
     }
 
-    pub fn size(&self) -> usize {self.headerLength as usize * 4}
+    /// Options up to End of Option List. Fails on any option `TcpOptionElement` can't hold.
+    pub fn optionElements(&self) -> Result<Vec<TcpOptionElement>, TCPHeaderError> {
+        let mut bytes = &self.options[..self.size() - 20];
+        let mut options = Vec::new();
+        while let Some(&kind) = bytes.first() {
+            match kind {
+                TCP_OPTION_ID_END => break,
+                TCP_OPTION_ID_NOP => {
+                    options.push(TcpOptionElement::Nop);
+                    bytes = &bytes[1..];
+                    continue;
+                },
+                _ => {},
+            }
+            // Lengths were checked by `checkOptions`, but the fields are public
+            let len = bytes.get(1).map_or(0, |&len| len as usize);
+            if len < 2 || len > bytes.len() {
+                return Err(TCPHeaderError::BadOptionLength { kind, len });
+            }
+            let body = &bytes[2..len];
+            options.push(match (kind, body.len()) {
+                (TCP_OPTION_ID_MAXIMUM_SEGMENT_SIZE, 2) => TcpOptionElement::MaximumSegmentSize(BigEndian::read_u16(body)),
+                (TCP_OPTION_ID_WINDOW_SCALE, 1) => TcpOptionElement::WindowScale(body[0]),
+                (TCP_OPTION_ID_SELECTIVE_ACK_PERMITTED, 0) => TcpOptionElement::SelectiveAcknowledgementPermitted,
+                (TCP_OPTION_ID_SELECTIVE_ACK, n) if n % 8 == 0 && (8..=32).contains(&n) => {
+                    let block = |index: usize| (BigEndian::read_u32(&body[8 * index..]), BigEndian::read_u32(&body[8 * index + 4..]));
+                    let mut rest = [None; 3];
+                    for (index, slot) in rest.iter_mut().enumerate().take(n / 8 - 1) {
+                        *slot = Some(block(index + 1));
+                    }
+                    TcpOptionElement::SelectiveAcknowledgement(block(0), rest)
+                },
+                (TCP_OPTION_ID_TIMESTAMP, 8) => TcpOptionElement::Timestamp(BigEndian::read_u32(body), BigEndian::read_u32(&body[4..])),
+                _ => return Err(TCPHeaderError::UnknownOption { kind, len }),
+            });
+            bytes = &bytes[len..];
+        }
+        Ok(options)
+    }
 }
 
 impl Default for TCPHeader {
@@ -244,6 +412,24 @@ impl Default for TCPHeader {
     }
 }
 
+/// Control bits as letters, highest bit first, e.g. "SA" for `0b00010010`
+pub(crate) fn flagLetters(controlBits: u8) -> String {
+    "CEUAPRSF".chars()
+        .enumerate()
+        .filter(|(i, _)| controlBits & (0x80 >> i) != 0)
+        .map(|(_, flag)| flag)
+        .collect()
+}
+
+/// ===> CONSTANTS
+const TCP_OPTION_ID_END                     : u8 = 0;
+const TCP_OPTION_ID_NOP                     : u8 = 1;
+const TCP_OPTION_ID_MAXIMUM_SEGMENT_SIZE    : u8 = 2;
+const TCP_OPTION_ID_WINDOW_SCALE            : u8 = 3;
+const TCP_OPTION_ID_SELECTIVE_ACK_PERMITTED : u8 = 4;
+const TCP_OPTION_ID_SELECTIVE_ACK           : u8 = 5;
+const TCP_OPTION_ID_TIMESTAMP               : u8 = 8;
+
 /// Different kinds of options that can be present in the options part of a TCP header.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum TcpOptionElement {
@@ -255,155 +441,3 @@ pub enum TcpOptionElement {
     ///Timestamp & echo (first number is the sender timestamp, the second the echo timestamp)
     Timestamp(u32, u32),
 }
-
-/*
- pub fn options(&self) -> &[u8] {
-        &self.options_buffer[..self.options_len()]
-    }
-
-    ///Sets the options (overwrites the current options) or returns an error when there is not enough space.
-    pub fn set_options(&mut self, options: &[TcpOptionElement]) -> Result<(), TcpOptionWriteError> {
-
-        //calculate the required size of the options
-        use crate::TcpOptionElement::*;
-        let required_length = options.iter().fold(0, |acc, ref x| {
-            acc + match x {
-                Nop => 1,
-                MaximumSegmentSize(_) => 4,
-                WindowScale(_) => 3,
-                SelectiveAcknowledgementPermitted => 2,
-                SelectiveAcknowledgement(_, rest) => {
-                    rest.iter().fold(10, |acc2, ref y| {
-                        match y {
-                            None => acc2,
-                            Some(_) => acc2 + 8
-                        }
-                    })
-                },
-                Timestamp(_, _) => 10,
-            }
-        });
-
-        if self.options_buffer.len() < required_length {
-            Err(TcpOptionWriteError::NotEnoughSpace(required_length))
-        } else {
-
-            //reset the options to null
-            self.options_buffer = [0;40];
-            self._data_offset = TCP_MINIMUM_DATA_OFFSET;
-
-            //write the options to the buffer
-            //note to whoever: I would have prefered to use std::io::Cursor as it would be less error
-            //                 prone. But just in case that "no std" support is added later lets
-            //                 not not rewrite it just yet with cursor.
-            let mut i = 0;
-            for element in options {
-                match element {
-                    Nop => {
-                        self.options_buffer[i] = TCP_OPTION_ID_NOP;
-                        i += 1;
-                    },
-                    MaximumSegmentSize(value) => {
-                        self.options_buffer[i] = TCP_OPTION_ID_MAXIMUM_SEGMENT_SIZE;
-                        i += 1;
-                        self.options_buffer[i] = 4;
-                        i += 1;
-                        BigEndian::write_u16(&mut self.options_buffer[i..i + 2], *value);
-                        i += 2;
-                    },
-                    WindowScale(value) => {
-                        self.options_buffer[i] = TCP_OPTION_ID_WINDOW_SCALE;
-                        i += 1;
-                        self.options_buffer[i] = 3;
-                        i += 1;
-                        self.options_buffer[i] = *value;
-                        i += 1;
-                    },
-                    SelectiveAcknowledgementPermitted => {
-                        self.options_buffer[i] = TCP_OPTION_ID_SELECTIVE_ACK_PERMITTED;
-                        i += 1;
-                        self.options_buffer[i] = 2;
-                        i += 1;
-                    },
-                    SelectiveAcknowledgement(first, rest) => {
-                        self.options_buffer[i] = TCP_OPTION_ID_SELECTIVE_ACK;
-                        i += 1;
-
-                        //write the length
-                        self.options_buffer[i] = rest.iter().fold(10, |acc, ref y| {
-                            match y {
-                                None => acc,
-                                Some(_) => acc + 8
-                            }
-                        });
-                        i += 1;
-
-                        //write first
-                        BigEndian::write_u32(&mut self.options_buffer[i..i + 4], first.0);
-                        i += 4;
-                        BigEndian::write_u32(&mut self.options_buffer[i..i + 4], first.1);
-                        i += 4;
-
-                        //write the rest
-                        for v in rest {
-                            match v {
-                                None => {},
-                                Some((a,b)) => {
-                                    BigEndian::write_u32(&mut self.options_buffer[i..i + 4], *a);
-                                    i += 4;
-                                    BigEndian::write_u32(&mut self.options_buffer[i..i + 4], *b);
-                                    i += 4;
-                                }
-                            }
-                        }
-                    },
-                    Timestamp(a, b) =>  {
-                        self.options_buffer[i] = TCP_OPTION_ID_TIMESTAMP;
-                        i += 1;
-                        self.options_buffer[i] = 10;
-                        i += 1;
-                        BigEndian::write_u32(&mut self.options_buffer[i..i + 4], *a);
-                        i += 4;
-                        BigEndian::write_u32(&mut self.options_buffer[i..i + 4], *b);
-                        i += 4;
-                    }
-                }
-            }
-            //set the new data offset
-            if i > 0 {
-                self._data_offset = (i / 4) as u8 + TCP_MINIMUM_DATA_OFFSET;
-                if i % 4 != 0 {
-                    self._data_offset += 1;
-                }
-            }
-            //done
-            Ok(())
-        }
-    }
-
-    ///Sets the options to the data given.
-    pub fn set_options_raw(&mut self, data: &[u8]) -> Result<(), TcpOptionWriteError> {
-        //check length
-        if self.options_buffer.len() < data.len() {
-            Err(TcpOptionWriteError::NotEnoughSpace(data.len()))
-        } else {
-            //reset all to zero to ensure padding
-            self.options_buffer = [0;40];
-
-            //set data & data_offset
-            self.options_buffer[..data.len()].copy_from_slice(data);
-            self._data_offset = (data.len() / 4) as u8 + TCP_MINIMUM_DATA_OFFSET;
-            if data.len() % 4 != 0 {
-                self._data_offset += 1;
-            }
-            Ok(())
-        }
-    }
-
-    ///Returns an iterator that allows to iterate through all known TCP header options.
-    pub fn options_iterator(&self) -> TcpOptionsIterator {
-        TcpOptionsIterator {
-            options: &self.options_buffer[..self.options_len()]
-        }
-    }
- */
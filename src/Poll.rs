@@ -0,0 +1,181 @@
+use crate::{TCPStream, TCPListener, TcpError, waitUntil};
+use crate::Time::Clock;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::{Duration, Instant};
+
+/// Identifies a registered source in returned events
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Token(pub usize);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Interest {
+    pub readable: bool,
+    pub writable: bool,
+}
+
+impl Interest {
+    pub const READABLE: Interest = Interest { readable: true, writable: false };
+    pub const WRITABLE: Interest = Interest { readable: false, writable: true };
+    pub const BOTH: Interest = Interest { readable: true, writable: true };
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Event {
+    token: Token,
+    readable: bool,
+    writable: bool,
+    closed: bool,
+}
+
+impl Event {
+    pub fn token(&self) -> Token { self.token }
+    pub fn is_readable(&self) -> bool { self.readable }
+    pub fn is_writable(&self) -> bool { self.writable }
+
+    /// Connection is gone. Further operations return its error.
+    /// Always reported irrespective of interest.
+    pub fn is_closed(&self) -> bool { self.closed }
+}
+
+#[derive(Debug, Default)]
+pub struct Events {
+    events: Vec<Event>,
+}
+
+impl Events {
+    pub fn new() -> Self { Events::default() }
+    pub fn iter(&self) -> std::slice::Iter<'_, Event> { self.events.iter() }
+    pub fn len(&self) -> usize { self.events.len() }
+    pub fn is_empty(&self) -> bool { self.events.is_empty() }
+    pub fn clear(&mut self) { self.events.clear(); }
+}
+
+impl<'a> IntoIterator for &'a Events {
+    type Item = &'a Event;
+    type IntoIter = std::slice::Iter<'a, Event>;
+    fn into_iter(self) -> Self::IntoIter { self.events.iter() }
+}
+
+#[derive(Debug, Default)]
+struct PollShared {
+    /// Events not yet returned by `poll`, merged per token
+    ready: Mutex<HashMap<Token, Event>>,
+    cond: Condvar,
+}
+
+/// Stored inside a stream/listener. `packetLoop` uses this to report readiness.
+#[derive(Debug)]
+pub(crate) struct Registration {
+    shared: Arc<PollShared>,
+    token: Token,
+    interest: Interest,
+}
+
+impl Registration {
+    pub(crate) fn notify(&self, readable: bool, writable: bool, closed: bool) {
+        let readable = readable && self.interest.readable;
+        let writable = writable && self.interest.writable;
+        if !(readable || writable || closed) { return; }
+
+        let mut ready = self.shared.ready.lock().unwrap();
+        let event = ready.entry(self.token).or_insert(Event {
+            token: self.token, readable: false, writable: false, closed: false
+        });
+        event.readable |= readable;
+        event.writable |= writable;
+        event.closed |= closed;
+        drop(ready);
+        self.shared.cond.notify_all();
+    }
+}
+
+/// ================================================
+///                      Poller
+/// ================================================
+/// Waits for readiness of many streams and listeners from a single thread.
+///
+/// Events are edge triggered. A source is reported once when it becomes ready,
+/// after that keep calling `read`/`write`/`accept` until they fail with `WouldBlock`
+/// before polling again. Sources should therefore be in non-blocking mode.
+#[derive(Clone)]
+pub struct Poller {
+    interface: usize,
+    shared: Arc<PollShared>,
+}
+
+impl Poller {
+    /// `interface` identifies the `ConnectionManager` sources must belong to
+    pub(crate) fn new(interface: usize) -> Self {
+        Poller { interface, shared: Arc::default() }
+    }
+
+    fn registration(&self, token: Token, interest: Interest) -> Registration {
+        Registration { shared: self.shared.clone(), token, interest }
+    }
+
+    fn checkInterface(&self, interface: usize) -> io::Result<()> {
+        if self.interface != interface {
+            return Err(TcpError::InvalidInput("Source belongs to another interface").into());
+        }
+        Ok(())
+    }
+
+    /// Start reporting readiness of `stream` with `token`. Replaces previous registration.
+    pub fn register(&self, stream: &TCPStream, token: Token, interest: Interest) -> io::Result<()> {
+        self.checkInterface(Arc::as_ptr(&stream.connectionManager) as usize)?;
+        let registration = self.registration(token, interest);
+
+        // Report what is ready already, later changes are reported by `packetLoop`
+        let connection = stream.connection.connection.lock().unwrap();
+        let closed = !connection.isHandled || connection.error.is_some();
+        registration.notify(!connection.incoming.is_empty() || connection.finReceived,
+                            connection.isWritable(),
+                            closed);
+        *stream.connection.poll.lock().unwrap() = Some(registration);
+        Ok(())
+    }
+
+    pub fn deregister(&self, stream: &TCPStream) -> io::Result<()> {
+        self.checkInterface(Arc::as_ptr(&stream.connectionManager) as usize)?;
+        *stream.connection.poll.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// Listener is readable when a connection is ready to be accepted
+    pub fn register_listener(&self, listener: &TCPListener, token: Token, interest: Interest) -> io::Result<()> {
+        self.checkInterface(Arc::as_ptr(&listener.connectionManager) as usize)?;
+        let registration = self.registration(token, interest);
+
+        let pendingQueue = listener.pending.pendingQueue.lock().unwrap();
+        registration.notify(!pendingQueue.is_empty(), false, false);
+        *listener.pending.poll.lock().unwrap() = Some(registration);
+        Ok(())
+    }
+
+    pub fn deregister_listener(&self, listener: &TCPListener) -> io::Result<()> {
+        self.checkInterface(Arc::as_ptr(&listener.connectionManager) as usize)?;
+        *listener.pending.poll.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// Blocks until at least one registered source is ready or `timeout` passes.
+    /// `events` is cleared and filled with ready sources. `None` waits forever.
+    pub fn poll(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+        events.clear();
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut ready = self.shared.ready.lock().unwrap();
+        while ready.is_empty() {
+            ready = match waitUntil(&self.shared.cond, ready, deadline, &Clock::System, TcpError::TimedOut) {
+                Ok(ready) => ready,
+                // Timing out is not an error, just nothing is ready
+                Err(_) => return Ok(())
+            };
+        }
+
+        events.events.extend(ready.drain().map(|(_, event)| event));
+        events.events.sort_by_key(|event| event.token);
+        Ok(())
+    }
+}
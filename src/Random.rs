@@ -0,0 +1,28 @@
+/// ================================================
+///                       Rng
+/// ================================================
+/// Small seeded generator (SplitMix64) for reproducible simulations.
+/// Not suitable for anything security related.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub(crate) fn nextU64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`
+    pub(crate) fn nextF64(&mut self) -> f64 {
+        (self.nextU64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
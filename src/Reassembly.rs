@@ -0,0 +1,193 @@
+use crate::Parser::{IPAddress, Ipv4Packet};
+use crate::Stats::Counters;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use log::debug;
+
+/// ===> CONSTANTS
+/// Incomplete datagrams are dropped this long after their first fragment arrived
+pub(crate) const REASSEMBLY_TIMEOUT      : Duration = Duration::from_secs(30);
+/// Bytes held by all incomplete datagrams together
+pub(crate) const REASSEMBLY_MEMORY_LIMIT : usize = 1 << 18; // 256KB
+/// Incomplete datagrams held at once
+pub(crate) const REASSEMBLY_MAX_DATAGRAMS: usize = 64;
+const MAX_DATAGRAM_SIZE                  : usize = 65535;
+
+/// Fragments belong to the same datagram when all of these match (RFC 791)
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+struct FragmentKey {
+    source         : IPAddress,
+    destination    : IPAddress,
+    identification : u16,
+    protocol       : u8,
+}
+
+#[derive(Debug)]
+struct Datagram {
+    /// Header of the fragment at offset 0, with its options
+    header    : Option<Vec<u8>>,
+    data      : Vec<u8>,
+    /// Sorted, disjoint byte ranges of `data` received so far
+    received  : Vec<(usize, usize)>,
+    /// Payload length, known once the last fragment arrived
+    totalLen  : Option<usize>,
+    firstSeen : Instant,
+}
+
+impl Datagram {
+    fn memory(&self) -> usize {
+        self.data.len() + self.header.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Whole IP packet, once every byte is in
+    fn assemble(&self) -> Option<Vec<u8>> {
+        let totalLen = self.totalLen?;
+        let header = self.header.as_ref()?;
+        if self.received != [(0, totalLen)] {
+            return None;
+        }
+        let mut packet = Vec::with_capacity(header.len() + totalLen);
+        packet.extend_from_slice(header);
+        packet.extend_from_slice(&self.data[..totalLen]);
+        let len = packet.len() as u16;
+        let mut view = Ipv4Packet::newUnchecked(&mut packet[..]);
+        view.setTotalLength(len);
+        view.setMoreFragments(false);
+        view.setFragmentOffset(0);
+        view.fillChecksum();
+        Some(packet)
+    }
+}
+
+/// ================================================
+///                IPv4 Reassembly
+/// ================================================
+/// Collects fragments until their datagram is complete.
+/// A fragment overlapping data already received (other than an exact duplicate)
+/// discards the whole datagram, the same as Linux, so there is never a choice
+/// between two versions of the same bytes. When memory or datagram limits are hit
+/// the oldest incomplete datagrams go first.
+#[derive(Debug)]
+pub(crate) struct Reassembler {
+    /// Ordered so eviction is the same on every run
+    datagrams : BTreeMap<FragmentKey, Datagram>,
+    memory    : usize,
+    counters  : Arc<Counters>,
+}
+
+impl Reassembler {
+    pub(crate) fn new(counters: Arc<Counters>) -> Self {
+        Reassembler { datagrams: BTreeMap::new(), memory: 0, counters }
+    }
+
+    /// Add a fragment (MF set or non zero offset). Returns the reassembled packet
+    /// once this fragment completes its datagram.
+    pub(crate) fn insert(&mut self, packet: &Ipv4Packet<&[u8]>, now: Instant) -> Option<Vec<u8>> {
+        Counters::inc(&self.counters.reasmReqds);
+        let key = FragmentKey {
+            source: packet.sourceIP(),
+            destination: packet.destinationIP(),
+            identification: packet.identification(),
+            protocol: packet.protocol(),
+        };
+        let payload = packet.payload();
+        let start = packet.fragmentOffset() as usize * 8;
+        let end = start + payload.len();
+        let last = !packet.moreFragments();
+
+        // Every fragment but the last carries a non zero multiple of 8 bytes
+        if end + packet.size() > MAX_DATAGRAM_SIZE || (!last && (payload.is_empty() || !payload.len().is_multiple_of(8))) {
+            return self.fail(key, "invalid fragment length");
+        }
+
+        let datagram = self.datagrams.entry(key).or_insert_with(|| Datagram {
+            header: None, data: Vec::new(), received: Vec::new(), totalLen: None, firstSeen: now,
+        });
+        if last {
+            if datagram.totalLen.is_some_and(|totalLen| totalLen != end)
+                || datagram.received.last().is_some_and(|&(_, received)| received > end) {
+                return self.fail(key, "conflicting datagram length");
+            }
+            datagram.totalLen = Some(end);
+        }
+        else if datagram.totalLen.is_some_and(|totalLen| end > totalLen) {
+            return self.fail(key, "fragment past end of datagram");
+        }
+
+        if let Some(&(from, to)) = datagram.received.iter().find(|&&(from, to)| start < to && from < end) {
+            if (from, to) == (start, end) {
+                // Retransmitted duplicate
+                return None;
+            }
+            return self.fail(key, "overlapping fragments");
+        }
+
+        let before = datagram.memory();
+        if datagram.data.len() < end {
+            datagram.data.resize(end, 0);
+        }
+        datagram.data[start..end].copy_from_slice(payload);
+        if start == 0 {
+            datagram.header = Some(packet.header().to_vec());
+        }
+        if start < end {
+            let at = datagram.received.iter().position(|&(from, _)| from > start).unwrap_or(datagram.received.len());
+            datagram.received.insert(at, (start, end));
+            // Merge ranges that now touch
+            datagram.received.dedup_by(|next, prev| {
+                if prev.1 == next.0 { prev.1 = next.1; true } else { false }
+            });
+        }
+        self.memory = self.memory + datagram.memory() - before;
+        if let (Some(header), Some(totalLen)) = (&datagram.header, datagram.totalLen) {
+            if header.len() + totalLen > MAX_DATAGRAM_SIZE {
+                return self.fail(key, "datagram too long");
+            }
+        }
+
+        if let Some(assembled) = datagram.assemble() {
+            self.remove(key);
+            Counters::inc(&self.counters.reasmOKs);
+            return Some(assembled);
+        }
+        self.enforceLimits();
+        None
+    }
+
+    /// Drop datagrams still incomplete after `REASSEMBLY_TIMEOUT`
+    pub(crate) fn expire(&mut self, now: Instant) {
+        let expired: Vec<FragmentKey> = self.datagrams.iter()
+            .filter(|(_, datagram)| now.duration_since(datagram.firstSeen) >= REASSEMBLY_TIMEOUT)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired {
+            self.fail(key, "reassembly timeout");
+        }
+    }
+
+    fn enforceLimits(&mut self) {
+        while self.memory > REASSEMBLY_MEMORY_LIMIT || self.datagrams.len() > REASSEMBLY_MAX_DATAGRAMS {
+            let oldest = self.datagrams.iter()
+                .min_by_key(|(_, datagram)| datagram.firstSeen)
+                .map(|(key, _)| *key);
+            match oldest {
+                Some(key) => { self.fail(key, "reassembly memory limit"); },
+                None => break,
+            }
+        }
+    }
+
+    fn fail(&mut self, key: FragmentKey, reason: &'static str) -> Option<Vec<u8>> {
+        debug!(reason = reason, source:% = key.source, id = key.identification; "Datagram dropped");
+        Counters::inc(&self.counters.reasmFails);
+        self.remove(key);
+        None
+    }
+
+    fn remove(&mut self, key: FragmentKey) {
+        if let Some(datagram) = self.datagrams.remove(&key) {
+            self.memory -= datagram.memory();
+        }
+    }
+}
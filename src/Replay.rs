@@ -0,0 +1,384 @@
+extern crate byteorder;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use crate::Capture::{LINKTYPE_RAW, PCAP_MAGIC, PCAPNG_SHB, PCAPNG_BYTE_ORDER, PCAPNG_IDB, PCAPNG_EPB, PCAPNG_OPT_EPB_FLAGS};
+use crate::Parser::IPAddress;
+use crate::TCPError::{TcpError, Result};
+use crate::VirtualNetwork::Device;
+use crate::Time::Clock;
+use crate::waitUntil;
+use std::collections::VecDeque;
+use std::fmt::{self, Debug, Formatter};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// ===> CONSTANTS
+const LINKTYPE_NULL         : u32 = 0;
+const LINKTYPE_ETHERNET     : u32 = 1;
+const LINKTYPE_LOOP         : u32 = 108;
+const LINKTYPE_IPV4         : u32 = 228;
+const PCAP_MAGIC_NANO       : u32 = 0xA1B2_3C4D;
+const PCAPNG_OPT_IF_TSRESOL : u16 = 9;
+const ETHERTYPE_IPV4        : u16 = 0x0800;
+
+/// A packet of a replayed session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayPacket {
+    /// For recorded packets time since first packet of the recording,
+    /// for responses time since replay started.
+    pub time : Duration,
+    /// IPv4 packet without any link layer header
+    pub data : Vec<u8>,
+}
+
+/// ================================================
+///                    PcapReplay
+/// ================================================
+/// `Device` playing back a recorded pcap/pcapng session into an `Interface`.
+/// Packets received by `local` are delivered at their recorded timing, the rest
+/// are kept as the expected output. Packets sent by the stack are collected as responses.
+///
+/// Clones share the same session, so keep one to inspect the results.
+/// Nothing is delivered before `start`, which gives time to bind listeners:
+/// ```no_run
+/// # use TCP::{Interface, IPAddress, PcapReplay};
+/// let replay = PcapReplay::open("session.pcapng", IPAddress::new(10, 12, 0, 1))?;
+/// let mut interface = Interface::with_device(replay.clone())?;
+/// let listener = interface.bind((IPAddress::UNSPECIFIED, 9000))?;
+/// replay.start();
+/// replay.wait(None)?;
+/// assert_eq!(replay.responses().len(), replay.expected().len());
+/// # Ok::<(), TCP::TcpError>(())
+/// ```
+#[derive(Clone)]
+pub struct PcapReplay {
+    shared: Arc<ReplayShared>,
+}
+
+struct ReplayShared {
+    state : Mutex<ReplayState>,
+    /// Notified when replay starts and once every recorded packet is processed
+    cond  : Condvar,
+}
+
+struct ReplayState {
+    inbound   : VecDeque<ReplayPacket>,
+    expected  : Vec<ReplayPacket>,
+    responses : Vec<ReplayPacket>,
+    /// `None` delivers packets as fast as they are read
+    speed     : Option<f64>,
+    /// Set by `PcapReplay::start`
+    start     : Option<Instant>,
+    finished  : bool,
+}
+
+impl PcapReplay {
+    /// Load the session recorded in file `path`. `local` is the address of the replayed stack.
+    pub fn open<P: AsRef<Path>>(path: P, local: IPAddress) -> Result<Self> {
+        PcapReplay::from_reader(File::open(path)?, local)
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R, local: IPAddress) -> Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let packets = if bytes.len() >= 4 && LittleEndian::read_u32(&bytes) == PCAPNG_SHB {
+            readPcapNg(&bytes)?
+        }
+        else {
+            readPcap(&bytes)?
+        };
+
+        let first = packets.iter().map(|packet| packet.time).min().unwrap_or(0);
+        let mut inbound = VecDeque::new();
+        let mut expected = Vec::new();
+        for packet in packets {
+            let isInbound = match packet.direction {
+                Some(direction) => direction,
+                None => packet.data.len() >= 20 && packet.data[16..20] == local.bytes,
+            };
+            let packet = ReplayPacket {
+                time: Duration::from_nanos((packet.time - first) as u64),
+                data: packet.data
+            };
+            if isInbound { inbound.push_back(packet); } else { expected.push(packet); }
+        }
+
+        let state = ReplayState { inbound, expected, responses: Vec::new(), speed: Some(1.0), start: None, finished: false };
+        Ok(PcapReplay { shared: Arc::new(ReplayShared { state: Mutex::new(state), cond: Condvar::new() }) })
+    }
+
+    /// `Some(1.0)` (default) keeps the recorded timing, `Some(10.0)` plays ten times faster
+    /// and `None` delivers every packet as soon as the stack is ready for it.
+    pub fn set_speed(&self, speed: Option<f64>) -> Result<()> {
+        if speed.is_some_and(|speed| speed.is_nan() || speed <= 0.0) {
+            return Err(TcpError::InvalidInput("Replay speed must be positive"));
+        }
+        self.shared.state.lock().unwrap().speed = speed;
+        Ok(())
+    }
+
+    /// Begin delivering packets. Recorded timing is relative to this call.
+    /// The interface must not be dropped before the replay is started,
+    /// as its packet loop is blocked until then.
+    pub fn start(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.start.get_or_insert_with(Instant::now);
+        self.shared.cond.notify_all();
+    }
+
+    /// Wait until every recorded packet has been processed by the stack.
+    /// Fails with `TimedOut` if that takes longer than `timeout`.
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<()> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.shared.state.lock().unwrap();
+        while !state.finished {
+            state = waitUntil(&self.shared.cond, state, deadline, &Clock::System, TcpError::TimedOut)?;
+        }
+        Ok(())
+    }
+
+    /// Packets the recorded stack sent, to compare `responses` against
+    pub fn expected(&self) -> Vec<ReplayPacket> {
+        self.shared.state.lock().unwrap().expected.clone()
+    }
+
+    /// Packets sent by the stack so far
+    pub fn responses(&self) -> Vec<ReplayPacket> {
+        self.shared.state.lock().unwrap().responses.clone()
+    }
+}
+
+impl Debug for PcapReplay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let state = self.shared.state.lock().unwrap();
+        f.debug_struct("PcapReplay")
+            .field("inbound", &state.inbound.len())
+            .field("expected", &state.expected.len())
+            .field("responses", &state.responses.len())
+            .field("speed", &state.speed)
+            .finish()
+    }
+}
+
+impl Device for PcapReplay {
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.shared.state.lock().unwrap();
+        while state.start.is_none() {
+            state = self.shared.cond.wait(state).unwrap();
+        }
+        let start = state.start.unwrap();
+        let packet = match state.inbound.pop_front() {
+            Some(packet) => packet,
+            None => {
+                // Stack asks for another packet, so it is done with the last one
+                state.finished = true;
+                self.shared.cond.notify_all();
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        };
+        let speed = state.speed;
+        drop(state);
+
+        if let Some(speed) = speed {
+            let due = start + packet.time.div_f64(speed);
+            let now = Instant::now();
+            if due > now {
+                sleep(due - now);
+            }
+        }
+
+        let len = packet.data.len().min(buf.len());
+        buf[..len].copy_from_slice(&packet.data[..len]);
+        Ok(len)
+    }
+
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.shared.state.lock().unwrap();
+        let time = state.start.map_or(Duration::from_secs(0), |start| start.elapsed());
+        state.responses.push(ReplayPacket { time, data: buf.to_vec() });
+        Ok(buf.len())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Device>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+/// ================================================
+///                   File Readers
+/// ================================================
+struct RecordedPacket {
+    /// Nanoseconds since epoch
+    time      : u128,
+    /// `Some(true)` if recorded as received, `None` if file does not say
+    direction : Option<bool>,
+    data      : Vec<u8>,
+}
+
+fn readU16(bytes: &[u8], bigEndian: bool) -> u16 {
+    if bigEndian { BigEndian::read_u16(bytes) } else { LittleEndian::read_u16(bytes) }
+}
+
+fn readU32(bytes: &[u8], bigEndian: bool) -> u32 {
+    if bigEndian { BigEndian::read_u32(bytes) } else { LittleEndian::read_u32(bytes) }
+}
+
+/// Strip link layer header. Returns `None` for anything other than IPv4.
+fn ipv4Packet(linkType: u32, data: &[u8]) -> Result<Option<Vec<u8>>> {
+    let packet = match linkType {
+        LINKTYPE_RAW | LINKTYPE_IPV4 => data,
+        LINKTYPE_ETHERNET => {
+            if data.len() < 14 || BigEndian::read_u16(&data[12..]) != ETHERTYPE_IPV4 {
+                return Ok(None);
+            }
+            &data[14..]
+        },
+        LINKTYPE_NULL | LINKTYPE_LOOP => {
+            if data.len() < 4 { return Ok(None); }
+            &data[4..]
+        },
+        _ => return Err(TcpError::InvalidInput("Unsupported pcap link type"))
+    };
+    if packet.len() < 20 || packet[0] >> 4 != 4 {
+        return Ok(None);
+    }
+
+    // Drop ethernet padding after the IP packet
+    let totalLength = BigEndian::read_u16(&packet[2..]) as usize;
+    let len = if totalLength >= 20 { totalLength.min(packet.len()) } else { packet.len() };
+    Ok(Some(packet[..len].to_vec()))
+}
+
+fn readPcap(bytes: &[u8]) -> Result<Vec<RecordedPacket>> {
+    if bytes.len() < 24 {
+        return Err(TcpError::InvalidInput("Not a pcap file"));
+    }
+    let (bigEndian, nanos) = match LittleEndian::read_u32(bytes) {
+        PCAP_MAGIC                              => (false, false),
+        PCAP_MAGIC_NANO                         => (false, true),
+        magic if magic.swap_bytes() == PCAP_MAGIC      => (true, false),
+        magic if magic.swap_bytes() == PCAP_MAGIC_NANO => (true, true),
+        _ => return Err(TcpError::InvalidInput("Not a pcap file"))
+    };
+    let linkType = readU32(&bytes[20..], bigEndian) & 0xFFFF;
+
+    let mut packets = Vec::new();
+    let mut offset = 24;
+    while offset < bytes.len() {
+        if bytes.len() - offset < 16 {
+            return Err(TcpError::InvalidInput("Truncated pcap file"));
+        }
+        let seconds = readU32(&bytes[offset..], bigEndian) as u128;
+        let fraction = readU32(&bytes[offset + 4..], bigEndian) as u128;
+        let capturedLength = readU32(&bytes[offset + 8..], bigEndian) as usize;
+        offset += 16;
+        if bytes.len() - offset < capturedLength {
+            return Err(TcpError::InvalidInput("Truncated pcap file"));
+        }
+
+        let time = seconds * 1_000_000_000 + if nanos { fraction } else { fraction * 1000 };
+        if let Some(data) = ipv4Packet(linkType, &bytes[offset..offset + capturedLength])? {
+            packets.push(RecordedPacket { time, direction: None, data });
+        }
+        offset += capturedLength;
+    }
+    Ok(packets)
+}
+
+fn readPcapNg(bytes: &[u8]) -> Result<Vec<RecordedPacket>> {
+    let mut packets = Vec::new();
+    // (link type, timestamp units per second) of every interface in current section
+    let mut interfaces: Vec<(u32, u128)> = Vec::new();
+    let mut bigEndian = false;
+    let mut offset = 0;
+    while offset < bytes.len() {
+        if bytes.len() - offset < 12 {
+            return Err(TcpError::InvalidInput("Truncated pcapng file"));
+        }
+        let blockType = LittleEndian::read_u32(&bytes[offset..]);
+        if blockType == PCAPNG_SHB {
+            bigEndian = match LittleEndian::read_u32(&bytes[offset + 8..]) {
+                PCAPNG_BYTE_ORDER => false,
+                magic if magic.swap_bytes() == PCAPNG_BYTE_ORDER => true,
+                _ => return Err(TcpError::InvalidInput("Bad pcapng byte order magic"))
+            };
+            interfaces.clear();
+        }
+        let blockType = readU32(&bytes[offset..], bigEndian);
+        let blockLength = readU32(&bytes[offset + 4..], bigEndian) as usize;
+        if blockLength < 12 || !blockLength.is_multiple_of(4) || bytes.len() - offset < blockLength {
+            return Err(TcpError::InvalidInput("Truncated pcapng file"));
+        }
+        let body = &bytes[offset + 8..offset + blockLength - 4];
+        offset += blockLength;
+
+        match blockType {
+            PCAPNG_IDB => {
+                if body.len() < 8 {
+                    return Err(TcpError::InvalidInput("Truncated pcapng interface block"));
+                }
+                let linkType = readU16(body, bigEndian) as u32;
+                let mut unitsPerSecond = 1_000_000;
+                for (code, value) in options(&body[8..], bigEndian) {
+                    if code == PCAPNG_OPT_IF_TSRESOL && !value.is_empty() {
+                        let exponent = (value[0] & 0x7F) as u32;
+                        let units = if value[0] & 0x80 == 0 { 10u128.checked_pow(exponent) } else { 1u128.checked_shl(exponent) };
+                        unitsPerSecond = units.ok_or(TcpError::InvalidInput("Unsupported pcapng timestamp resolution"))?;
+                    }
+                }
+                interfaces.push((linkType, unitsPerSecond));
+            },
+            PCAPNG_EPB => {
+                if body.len() < 20 {
+                    return Err(TcpError::InvalidInput("Truncated pcapng packet block"));
+                }
+                let interface = readU32(body, bigEndian) as usize;
+                let &(linkType, unitsPerSecond) = interfaces.get(interface)
+                    .ok_or(TcpError::InvalidInput("Packet on unknown pcapng interface"))?;
+                let timestamp = ((readU32(&body[4..], bigEndian) as u128) << 32) | readU32(&body[8..], bigEndian) as u128;
+                let capturedLength = readU32(&body[12..], bigEndian) as usize;
+                if body.len() - 20 < capturedLength {
+                    return Err(TcpError::InvalidInput("Truncated pcapng packet block"));
+                }
+
+                let padded = (capturedLength + 3) & !3;
+                let mut direction = None;
+                for (code, value) in options(body.get(20 + padded..).unwrap_or(&[]), bigEndian) {
+                    if code == PCAPNG_OPT_EPB_FLAGS && value.len() >= 4 {
+                        direction = match readU32(value, bigEndian) & 0b11 {
+                            0b01 => Some(true),
+                            0b10 => Some(false),
+                            _ => None
+                        };
+                    }
+                }
+
+                let time = timestamp * 1_000_000_000 / unitsPerSecond;
+                if let Some(data) = ipv4Packet(linkType, &body[20..20 + capturedLength])? {
+                    packets.push(RecordedPacket { time, direction, data });
+                }
+            },
+            // Section headers were handled above, other blocks carry nothing we replay
+            _ => {}
+        }
+    }
+    Ok(packets)
+}
+
+/// (code, value) of every option in a pcapng options list
+fn options(mut bytes: &[u8], bigEndian: bool) -> Vec<(u16, &[u8])> {
+    let mut options = Vec::new();
+    while bytes.len() >= 4 {
+        let code = readU16(bytes, bigEndian);
+        let len = readU16(&bytes[2..], bigEndian) as usize;
+        if code == 0 || bytes.len() - 4 < len {
+            break;
+        }
+        options.push((code, &bytes[4..4 + len]));
+        bytes = &bytes[4 + ((len + 3) & !3).min(bytes.len() - 4)..];
+    }
+    options
+}
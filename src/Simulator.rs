@@ -0,0 +1,291 @@
+use crate::{ConnectionManager, Interface, TIMER_TICK};
+use crate::Capture::Direction;
+use crate::EphemeralPorts::PortAllocator;
+use crate::Identification::Identifications;
+use crate::Impair::{Impairer, Impairment};
+use crate::Parser::IPAddress;
+use crate::Random::Rng;
+use crate::TCPError::{TcpError, Result};
+use crate::Time::{Clock, VirtualClock};
+use crate::VirtualNetwork::{Device, Nic, RxChecksum};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, VecDeque};
+use std::fmt::{self, Debug, Formatter};
+use std::io;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use log::debug;
+
+/// ================================================
+///                    Simulation
+/// ================================================
+/// Deterministic network of interfaces and raw endpoints sharing one simulated link.
+/// Nothing runs in the background: packets are delivered and timers fire only while
+/// `advance` or `run_until` move the virtual clock, so a run is reproducible from its seed.
+///
+/// There is no other thread to make progress while a call blocks, so use streams
+/// and listeners of simulated interfaces in non-blocking mode, or block in another
+/// thread while this one advances. Read, write and accept timeouts count virtual time.
+pub struct Simulation {
+    clock    : Arc<VirtualClock>,
+    link     : Arc<Mutex<Link>>,
+    nodes    : BTreeMap<IPAddress, Node>,
+    /// Virtual time of next timer tick
+    nextTick : Duration,
+    rng      : Rng,
+}
+
+type Inbox = Arc<Mutex<VecDeque<(Duration, Vec<u8>)>>>;
+
+enum Node {
+    Stack(Weak<ConnectionManager>),
+    Endpoint(Inbox),
+}
+
+struct Link {
+    inFlight : BinaryHeap<Reverse<InFlight>>,
+    /// Orders packets due at the same time by when they were sent
+    sequence : u64,
+    impairer : Impairer,
+    /// MTU of every simulated device on the link
+    mtu      : usize,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct InFlight {
+    due      : Duration,
+    sequence : u64,
+    packet   : Vec<u8>,
+}
+
+impl Link {
+    fn transmit(&mut self, now: Duration, packet: &[u8]) {
+        for delay in self.impairer.process(now, packet.len()) {
+            self.sequence += 1;
+            self.inFlight.push(Reverse(InFlight { due: now + delay, sequence: self.sequence, packet: packet.to_vec() }));
+        }
+    }
+}
+
+impl Simulation {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let impairment = Impairment { latency: Duration::from_millis(1), ..Impairment::default() };
+        let link = Link {
+            inFlight: BinaryHeap::new(),
+            sequence: 0,
+            impairer: Impairer::new(impairment, rng.nextU64()),
+            mtu: 1500,
+        };
+        Simulation {
+            clock: Arc::new(VirtualClock::new()),
+            link: Arc::new(Mutex::new(link)),
+            nodes: BTreeMap::new(),
+            nextTick: TIMER_TICK,
+            rng,
+        }
+    }
+
+    /// One way delay of the link (default 1ms). Every packet is delayed by
+    /// a further random amount of up to `jitter`, which can reorder packets.
+    pub fn set_latency(&mut self, latency: Duration, jitter: Duration) {
+        let config = &mut self.link.lock().unwrap().impairer.config;
+        config.latency = latency;
+        config.jitter = jitter;
+    }
+
+    /// Impair every packet crossing the link, in both directions.
+    /// Replaces latency and jitter set by `set_latency`.
+    pub fn set_impairment(&mut self, impairment: Impairment) {
+        self.link.lock().unwrap().impairer.config = impairment;
+    }
+
+    /// Largest packet interfaces send on the link (default 1500). They fragment
+    /// larger packets when DF is clear. Endpoints send packets of any size.
+    pub fn set_mtu(&mut self, mtu: usize) {
+        self.link.lock().unwrap().mtu = mtu;
+    }
+
+    /// Attach a new stack with address `addr` to the link
+    pub fn add_interface(&mut self, addr: IPAddress) -> Result<Interface> {
+        self.checkAddress(addr)?;
+        let device = SimulatedDevice { link: self.link.clone(), clock: self.clock.clone() };
+        let ports = PortAllocator::withSecret(self.rng.nextU64());
+        let nic = Nic::new(Box::new(device), Arc::default(), Identifications::withSecret(self.rng.nextU64()));
+        let connectionManager = Arc::new(ConnectionManager {
+            ports: Mutex::new(ports),
+            ..ConnectionManager::new(nic, Clock::Virtual(self.clock.clone()))
+        });
+        self.nodes.insert(addr, Node::Stack(Arc::downgrade(&connectionManager)));
+        Ok(Interface { thread: None, timer: None, connectionManager })
+    }
+
+    /// Attach a raw endpoint with address `addr`, which sends and receives
+    /// whole IP packets. Useful to drive a stack with hand made packets.
+    pub fn add_endpoint(&mut self, addr: IPAddress) -> Result<Endpoint> {
+        self.checkAddress(addr)?;
+        let inbox = Inbox::default();
+        self.nodes.insert(addr, Node::Endpoint(inbox.clone()));
+        Ok(Endpoint { addr, link: self.link.clone(), clock: self.clock.clone(), inbox })
+    }
+
+    /// Virtual time since the simulation was created
+    pub fn elapsed(&self) -> Duration {
+        self.clock.elapsed()
+    }
+
+    /// Run every event due within `duration` and move the clock forward by `duration`
+    pub fn advance(&mut self, duration: Duration) {
+        let target = self.elapsed() + duration;
+        while self.step(target) {}
+        self.clock.advanceTo(target);
+    }
+
+    /// Run events until `done` returns true. Gives up and returns false
+    /// once `limit` of virtual time has passed.
+    pub fn run_until<F: FnMut() -> bool>(&mut self, limit: Duration, mut done: F) -> bool {
+        let deadline = self.elapsed() + limit;
+        loop {
+            if done() {
+                return true;
+            }
+            if !self.step(deadline) {
+                self.clock.advanceTo(deadline);
+                return done();
+            }
+        }
+    }
+
+    fn checkAddress(&self, addr: IPAddress) -> Result<()> {
+        if addr.isUnspecified() {
+            return Err(TcpError::InvalidInput("Simulated node needs a specific address"));
+        }
+        if self.nodes.contains_key(&addr) {
+            return Err(TcpError::AddressInUse);
+        }
+        Ok(())
+    }
+
+    /// Run the next event (packet delivery or timer tick) if it is due by `until`.
+    /// Packets go before a timer tick due at the same time.
+    fn step(&mut self, until: Duration) -> bool {
+        let nextPacket = self.link.lock().unwrap().inFlight.peek().map(|Reverse(inFlight)| inFlight.due);
+        let (due, isPacket) = match nextPacket {
+            Some(due) if due <= self.nextTick => (due, true),
+            _ => (self.nextTick, false)
+        };
+        if due > until {
+            return false;
+        }
+
+        self.clock.advanceTo(due);
+        if isPacket {
+            // Link must be unlocked while delivering as the stack may answer right away
+            let Reverse(inFlight) = self.link.lock().unwrap().inFlight.pop().unwrap();
+            self.deliver(inFlight.packet);
+        }
+        else {
+            self.tick();
+            self.nextTick += TIMER_TICK;
+        }
+        true
+    }
+
+    fn deliver(&mut self, packet: Vec<u8>) {
+        let destination = if packet.len() >= 20 {
+            IPAddress { bytes: [packet[16], packet[17], packet[18], packet[19]] }
+        }
+        else {
+            IPAddress::UNSPECIFIED
+        };
+
+        match self.nodes.get(&destination) {
+            Some(Node::Stack(connectionManager)) => {
+                if let Some(connectionManager) = connectionManager.upgrade() {
+                    if *connectionManager.terminate.lock().unwrap() {
+                        return;
+                    }
+                    connectionManager.nic.lock().unwrap().record(Direction::In, &packet);
+                    connectionManager.onPacket(&packet, RxChecksum::Unverified);
+                }
+            },
+            Some(Node::Endpoint(inbox)) => {
+                inbox.lock().unwrap().push_back((self.clock.elapsed(), packet));
+            },
+            None => {
+                debug!(reason = "no route", destination:% = destination; "Packet dropped");
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        let mut buf = [0u8; 1500];
+        for node in self.nodes.values() {
+            if let Node::Stack(connectionManager) = node {
+                if let Some(connectionManager) = connectionManager.upgrade() {
+                    if !*connectionManager.terminate.lock().unwrap() {
+                        connectionManager.onTick(&mut buf);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// ================================================
+///                     Endpoint
+/// ================================================
+/// Raw node of a `Simulation`
+pub struct Endpoint {
+    addr  : IPAddress,
+    link  : Arc<Mutex<Link>>,
+    clock : Arc<VirtualClock>,
+    inbox : Inbox,
+}
+
+impl Endpoint {
+    pub fn addr(&self) -> IPAddress {
+        self.addr
+    }
+
+    /// Put an IP packet on the link
+    pub fn send(&self, packet: &[u8]) {
+        self.link.lock().unwrap().transmit(self.clock.elapsed(), packet);
+    }
+
+    /// Oldest packet delivered to this endpoint, with the virtual time it arrived
+    pub fn recv(&self) -> Option<(Duration, Vec<u8>)> {
+        self.inbox.lock().unwrap().pop_front()
+    }
+}
+
+/// Device of a simulated interface. Packets are delivered by `Simulation` instead of `recv`.
+struct SimulatedDevice {
+    link  : Arc<Mutex<Link>>,
+    clock : Arc<VirtualClock>,
+}
+
+impl Debug for SimulatedDevice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimulatedDevice").finish()
+    }
+}
+
+impl Device for SimulatedDevice {
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Simulated devices are driven by Simulation"))
+    }
+
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.link.lock().unwrap().transmit(self.clock.elapsed(), buf);
+        Ok(buf.len())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Device>> {
+        Ok(Box::new(SimulatedDevice { link: self.link.clone(), clock: self.clock.clone() }))
+    }
+
+    fn mtu(&self) -> usize {
+        self.link.lock().unwrap().mtu
+    }
+}
@@ -0,0 +1,172 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// ================================================
+///                Interface Counters
+/// ================================================
+/// Shared by the interface threads and every connection on it.
+/// Names follow the TCP MIB (RFC 4022) and IP MIB (RFC 4293) where one exists.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub(crate) passiveOpens         : AtomicU64,
+    pub(crate) attemptFails         : AtomicU64,
+    pub(crate) estabResets          : AtomicU64,
+    pub(crate) inSegs               : AtomicU64,
+    pub(crate) outSegs              : AtomicU64,
+    pub(crate) inErrs               : AtomicU64,
+    pub(crate) outRsts              : AtomicU64,
+    pub(crate) inRsts               : AtomicU64,
+    pub(crate) checksumErrors       : AtomicU64,
+    pub(crate) reasmReqds           : AtomicU64,
+    pub(crate) reasmOKs             : AtomicU64,
+    pub(crate) reasmFails           : AtomicU64,
+    pub(crate) fragOKs              : AtomicU64,
+    pub(crate) fragFails            : AtomicU64,
+    pub(crate) fragCreates          : AtomicU64,
+    pub(crate) drops                : [AtomicU64; DropReason::COUNT],
+}
+
+/// Why an incoming packet was thrown away
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum DropReason {
+    Malformed,
+    Checksum,
+    NotTcp,
+    NoListener,
+    NotSyn,
+    Unacceptable,
+    ResetOutOfWindow,
+}
+
+impl DropReason {
+    const COUNT: usize = 7;
+}
+
+impl Counters {
+    pub(crate) fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dropped(&self, reason: DropReason) {
+        Counters::inc(&self.drops[reason as usize]);
+    }
+
+    pub(crate) fn snapshot(&self, currEstab: u64) -> InterfaceStats {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let drop = |reason: DropReason| get(&self.drops[reason as usize]);
+        InterfaceStats {
+            passiveOpens: get(&self.passiveOpens),
+            attemptFails: get(&self.attemptFails),
+            estabResets: get(&self.estabResets),
+            currEstab,
+            inSegs: get(&self.inSegs),
+            outSegs: get(&self.outSegs),
+            inErrs: get(&self.inErrs),
+            outRsts: get(&self.outRsts),
+            inRsts: get(&self.inRsts),
+            checksumErrors: get(&self.checksumErrors),
+            reasmReqds: get(&self.reasmReqds),
+            reasmOKs: get(&self.reasmOKs),
+            reasmFails: get(&self.reasmFails),
+            fragOKs: get(&self.fragOKs),
+            fragFails: get(&self.fragFails),
+            fragCreates: get(&self.fragCreates),
+            drops: DropStats {
+                malformed: drop(DropReason::Malformed),
+                checksum: drop(DropReason::Checksum),
+                notTcp: drop(DropReason::NotTcp),
+                noListener: drop(DropReason::NoListener),
+                notSyn: drop(DropReason::NotSyn),
+                unacceptable: drop(DropReason::Unacceptable),
+                resetOutOfWindow: drop(DropReason::ResetOutOfWindow),
+            }
+        }
+    }
+}
+
+/// Snapshot of interface counters returned by `Interface::stats`
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct InterfaceStats {
+    /// Connections opened by other side (SYN received on a listener)
+    pub passiveOpens    : u64,
+    /// Connections that failed during handshake
+    pub attemptFails    : u64,
+    /// Connections reset from ESTABLISHED or CLOSE-WAIT
+    pub estabResets     : u64,
+    /// Connections currently in ESTABLISHED or CLOSE-WAIT
+    pub currEstab       : u64,
+    pub inSegs          : u64,
+    pub outSegs         : u64,
+    /// Segments received with errors (malformed or bad checksum)
+    pub inErrs          : u64,
+    pub outRsts         : u64,
+    pub inRsts          : u64,
+    pub checksumErrors  : u64,
+    /// IP fragments received that needed reassembly
+    pub reasmReqds      : u64,
+    /// Datagrams successfully reassembled
+    pub reasmOKs        : u64,
+    /// Datagrams discarded by reassembly (timeout, overlap, limits, bad lengths)
+    pub reasmFails      : u64,
+    /// Outgoing packets larger than the device MTU that were fragmented
+    pub fragOKs         : u64,
+    /// Outgoing packets larger than the device MTU that could not be fragmented (DF set)
+    pub fragFails       : u64,
+    /// Fragments sent
+    pub fragCreates     : u64,
+    pub drops           : DropStats,
+}
+
+/// Incoming packets dropped, by reason
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct DropStats {
+    /// Headers could not be parsed
+    pub malformed       : u64,
+    pub checksum        : u64,
+    /// Not a TCP packet
+    pub notTcp          : u64,
+    /// No listener on destination port
+    pub noListener      : u64,
+    /// New connection not starting with SYN
+    pub notSyn          : u64,
+    /// Sequence/acknowledgement number outside of window
+    pub unacceptable    : u64,
+    pub resetOutOfWindow: u64,
+}
+
+/// ================================================
+///                Connection Stats
+/// ================================================
+/// Returned by `TCPStream::stats`
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Payload bytes transmitted, excluding retransmissions
+    pub bytesSent       : u64,
+    /// Payload bytes acknowledged by other side
+    pub bytesAcked      : u64,
+    /// Payload bytes received in order
+    pub bytesReceived   : u64,
+    pub segmentsIn      : u64,
+    pub segmentsOut     : u64,
+    /// Smoothed round trip time (RFC 6298). `None` until first sample.
+    pub rtt             : Option<Duration>,
+    /// Round trip time variation
+    pub rttVar          : Option<Duration>,
+}
+
+impl ConnectionStats {
+    /// Update smoothed RTT with a new measurement as in RFC 6298 section 2
+    pub(crate) fn sampleRtt(&mut self, sample: Duration) {
+        match (self.rtt, self.rttVar) {
+            (Some(srtt), Some(rttVar)) => {
+                let delta = srtt.abs_diff(sample);
+                self.rttVar = Some(rttVar * 3 / 4 + delta / 4);
+                self.rtt = Some(srtt * 7 / 8 + sample / 8);
+            },
+            _ => {
+                self.rtt = Some(sample);
+                self.rttVar = Some(sample / 2);
+            }
+        }
+    }
+}
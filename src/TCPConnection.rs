@@ -1,14 +1,49 @@
 use super::{Parser::*, VirtualNetwork::*, queue::Queue};
 use std::collections::VecDeque;
+use std::cmp::min;
 use crate::Quad;
 
 use std::fmt::{Debug, Formatter, Display};
+use std::io;
+use crate::TCPError::{TcpError, Result};
+use crate::Time::Clock;
+use crate::Stats::{Counters, ConnectionStats, DropReason};
+use crate::Netstat::{ConnectionInfo, SendSequenceInfo, RecvSequenceInfo, TimerInfo};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use log::{trace, debug};
 
 /// ===> CONSTANTS
 const DEFAULT_WINDOW_SIZE   : u16 = 10;
 pub(crate) const OUTGOING_BUFFER_LIMIT : usize = 1 << 18; // 256KB
 pub(crate) const INCOMING_BUFFER_LIMIT : usize = 1 << 18; // 256KB
+/// TTL of outgoing packets until changed with `TCPStream::set_ttl`
+const DEFAULT_TTL           : u8 = 64;
 
+/// ===> KEEPALIVE
+///
+/// A connection which has not heard anything from the other side for `idle`
+/// starts sending keepalive probes every `interval`. If `probes` of them go
+/// unanswered the other side is considered dead and the connection is aborted.
+/// Defaults follow RFC 1122 (2 hours idle) and the usual Linux interval/count.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Keepalive {
+    pub idle     : Duration,
+    pub interval : Duration,
+    pub probes   : u32,
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Keepalive {
+            idle     : Duration::from_secs(2 * 60 * 60),
+            interval : Duration::from_secs(75),
+            probes   : 9,
+        }
+    }
+}
+
+/// ```text
 ///                                            Transmission Control Protocol
 ///                                                 Functional Specification
 ///
@@ -61,6 +96,7 @@ pub(crate) const INCOMING_BUFFER_LIMIT : usize = 1 << 18; // 256KB
 ///                               +---------+                   +---------+
 ///
 ///                       TCP Connection State Diagram
+/// ```
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum TCPState{
@@ -79,10 +115,12 @@ pub enum TCPState{
 
 ///  ===> Send Sequence Space
 ///
+/// ```text
 ///            1         2          3          4
 ///           ----------|----------|----------|----------
 ///                  SND.UNA    SND.NXT    SND.UNA
 ///                                       +SND.WND
+/// ```
 ///
 /// 1 - old sequence numbers which have been acknowledged
 /// 2 - sequence numbers of unacknowledged data
@@ -129,10 +167,12 @@ impl SendSequenceSpace{
 
 ///   ===> Receive Sequence Space
 ///
+/// ```text
 ///                  1          2          3
 ///              ----------|----------|----------
 ///                     RCV.NXT    RCV.NXT
 ///                               +RCV.WND
+/// ```
 ///
 ///   1 - old sequence numbers which have been acknowledged
 ///   2 - sequence numbers allowed for new reception
@@ -165,11 +205,17 @@ impl RecvSequenceSpace{
 
 pub struct Connection{
     pub(crate) isHandled: bool,
+    /// Set when the connection is torn down by us. Reads and writes report it.
+    pub(crate) error: Option<TcpError>,
     state: TCPState,
     send: SendSequenceSpace,
     recv: RecvSequenceSpace,
     tcph: TCPHeader,
-    iph: IPHeader,
+    /// Template for outgoing IP headers, holds per connection IP settings
+    pub(crate) iph: IPHeader,
+
+    /// Peer sent FIN, reads return end of file once `incoming` is drained
+    pub(crate) finReceived: bool,
 
     // Incoming packets that user haven't read
     // pub(crate) incoming: Queue<u8>,
@@ -178,6 +224,35 @@ pub struct Connection{
     // Unacked packets of data
     // pub(crate) outgoing: DeQueue<u8>,
     pub(crate) outgoing: VecDeque<u8>,
+
+    // Read/write on TCPStream return `WouldBlock` instead of waiting
+    pub(crate) nonblocking: bool,
+
+    // Blocking read/write on TCPStream give up after these
+    pub(crate) readTimeout: Option<Duration>,
+    pub(crate) writeTimeout: Option<Duration>,
+
+    // User Timeout (RFC 5482)
+    // Maximum time sent sequence space may stay unacknowledged before connection is aborted.
+    // Runs while `send.una != send.nxt`. Data in `outgoing` is never sent, so only
+    // our SYN and FIN start it.
+    pub(crate) userTimeout: Option<Duration>,
+    lastAckProgress: Instant,
+
+    // Keepalive
+    pub(crate) keepalive: Option<Keepalive>,
+    lastRecv: Instant,
+    lastProbe: Instant,
+    probesSent: u32,
+
+    // Statistics
+    pub(crate) stats: ConnectionStats,
+    counters: Arc<Counters>,
+    /// Sequence number whose acknowledgement gives next RTT sample and when it was sent
+    rttProbe: Option<(u32, Instant)>,
+
+    /// Time source of the interface
+    clock: Clock,
 }
 
 impl Debug for Connection {
@@ -187,24 +262,40 @@ impl Debug for Connection {
 }
 
 impl Connection{
-    pub fn new(iph: &IPHeader, tcph: &TCPHeader, passiveOpen: bool) -> Option<Connection> {
-        if !tcph.syn { return None; }
+    pub(crate) fn new(iph: &Ipv4Packet<&[u8]>, tcph: &TcpSegment<&[u8]>, passiveOpen: bool, counters: Arc<Counters>, clock: Clock) -> Result<Connection> {
+        if !tcph.syn() { return Err(TcpError::MalformedPacket("Connection must start with SYN")); }
         let iss = 0;
         let state = if passiveOpen { TCPState::Listen } else { TCPState::Closed };
-        Some(Connection{
+        let now = clock.now();
+        Ok(Connection{
             isHandled: false,
+            error: None,
             state,
             send: SendSequenceSpace::new(0),
             recv: RecvSequenceSpace{
-                irs: tcph.sequenceNumber,
-                nxt: tcph.sequenceNumber + 1,
-                wnd: tcph.window,
+                irs: tcph.sequenceNumber(),
+                nxt: tcph.sequenceNumber() + 1,
+                wnd: tcph.window(),
                 up : false
             },
-            tcph: TCPHeader::new(tcph.destinationPort, tcph.sourcePort, iss, DEFAULT_WINDOW_SIZE),
-            iph: IPHeader::new(iph.destinationIP, iph.sourceIP, IPProtocol::Tcp, 64, 20),
+            tcph: TCPHeader::new(tcph.destinationPort(), tcph.sourcePort(), iss, DEFAULT_WINDOW_SIZE),
+            iph: IPHeader::new(iph.destinationIP(), iph.sourceIP(), IPProtocol::Tcp, DEFAULT_TTL, 0),
+            finReceived: false,
             incoming: VecDeque::new(),
-            outgoing: VecDeque::new()
+            outgoing: VecDeque::new(),
+            nonblocking: false,
+            readTimeout: None,
+            writeTimeout: None,
+            userTimeout: None,
+            lastAckProgress: now,
+            keepalive: None,
+            lastRecv: now,
+            lastProbe: now,
+            probesSent: 0,
+            stats: ConnectionStats::default(),
+            counters,
+            rttProbe: None,
+            clock,
         })
     }
 
@@ -215,50 +306,53 @@ impl Connection{
         }
     }
 
-    fn handleReset(&mut self, buff: &mut [u8], tcph: TCPHeader, nic: &mut VNC) {
+    fn handleReset(&mut self, buff: &mut [u8], tcph: &TcpSegment<&[u8]>, nic: &mut Nic) {
         self.tcph.rst = true;
         self.write(nic, buff, &[]);
     }
 
-    fn handleListen(&mut self, buff: &mut [u8], nic: &mut VNC) {
+    fn handleListen(&mut self, buff: &mut [u8], nic: &mut Nic) {
         self.tcph.syn = true;
         self.tcph.ack = true;
         self.tcph.acknowledgementNumber = self.recv.nxt;
         self.tcph.sequenceNumber = self.send.iss;
-        self.state = TCPState::SynRcvd;
+        self.setState(TCPState::SynRcvd);
         self.write(nic, buff, &[]);
+        self.rttProbe = Some((self.send.nxt, self.clock.now()));
 
         // Reset control bits
         self.tcph.syn = false;
         self.tcph.ack = false;
     }
 
-    fn handleSynRcvd(&mut self, buff: &mut [u8], tcph: TCPHeader, nic: &mut VNC) {
-        if tcph.ack {
-            self.state = TCPState::Estab;
+    fn handleSynRcvd(&mut self, buff: &mut [u8], tcph: &TcpSegment<&[u8]>, nic: &mut Nic) {
+        if tcph.ack() {
+            self.setState(TCPState::Estab);
         }
     }
 
-    fn handleLastAck(&mut self, buff: &mut [u8], tcph: TCPHeader, nic: &mut VNC) {
-        if tcph.ack {
-            self.state = TCPState::Closed;
+    fn handleLastAck(&mut self, buff: &mut [u8], tcph: &TcpSegment<&[u8]>, nic: &mut Nic) {
+        if tcph.ack() {
+            self.setState(TCPState::Closed);
         }
     }
 
-    fn handleEstab(&mut self, buff: &mut [u8], tcph: TCPHeader, dataStart: usize, nic: &mut VNC) {
+    fn handleEstab(&mut self, buff: &mut [u8], tcph: &TcpSegment<&[u8]>, nic: &mut Nic) {
         // Temporarily print data as char
-        // let data = String::from_utf8_lossy(&buff[dataStart..]);
+        // let data = String::from_utf8_lossy(tcph.payload());
         // print!("{}", data);
-        let dataSize= buff.len() - dataStart;
+        let dataSize = tcph.payload().len();
 
         if dataSize > 0 {
-            self.incoming.extend(buff[dataStart..].iter());
+            self.stats.bytesReceived += dataSize as u64;
+            self.incoming.extend(tcph.payload().iter());
         }
 
-        if tcph.fin {
+        if tcph.fin() {
             // Request for Closing Connection
+            self.finReceived = true;
             self.recv.nxt = Self::addWrapping(self.recv.nxt, 1);
-            self.state = TCPState::CloseWait;
+            self.setState(TCPState::CloseWait);
         }
         else {
             self.recv.nxt = Self::addWrapping(self.recv.nxt, dataSize);
@@ -266,7 +360,7 @@ impl Connection{
 
         // Send Acknoledgement
         self.tcph.ack = true;
-        self.tcph.sequenceNumber = tcph.acknowledgementNumber;
+        self.tcph.sequenceNumber = tcph.acknowledgementNumber();
         self.tcph.acknowledgementNumber = self.recv.nxt;
         self.write(nic, buff, &[]);
 
@@ -278,47 +372,98 @@ impl Connection{
         }
     }
 
-    fn handleCloseWait(&mut self, buff: &mut [u8], tcph: TCPHeader, nic: &mut VNC){
+    fn handleCloseWait(&mut self, buff: &mut [u8], tcph: &TcpSegment<&[u8]>, nic: &mut Nic){
         self.tcph.fin = true;
         self.tcph.ack = true;
-        self.send.nxt = Self::addWrapping(self.send.nxt, 1);
+        self.advanceSendNext(1);
         self.write(nic, buff, &[]);
-        self.state = TCPState::LastAck;
+        self.setState(TCPState::LastAck);
     }
 
-    fn handleFinWait1(&mut self, buff: &mut [u8], tcph: TCPHeader, nic: &mut VNC){
-        if tcph.ack {
-            self.state = TCPState::FinWait2;
+    fn handleFinWait1(&mut self, buff: &mut [u8], tcph: &TcpSegment<&[u8]>, nic: &mut Nic){
+        if tcph.ack() {
+            self.setState(TCPState::FinWait2);
         }
     }
 
-    fn handleFinWait2(&mut self, buff: &mut [u8], tcph: TCPHeader, nic: &mut VNC){
-        if tcph.fin {
+    fn handleFinWait2(&mut self, buff: &mut [u8], tcph: &TcpSegment<&[u8]>, nic: &mut Nic){
+        if tcph.fin() {
+            self.finReceived = true;
             self.recv.nxt = Self::addWrapping(self.recv.nxt, 1);
             self.tcph.fin = false;
             self.tcph.ack = true;
-            self.tcph.sequenceNumber = tcph.acknowledgementNumber;
+            self.tcph.sequenceNumber = tcph.acknowledgementNumber();
             self.tcph.acknowledgementNumber = self.recv.nxt;
             self.write(nic, buff, &[]);
             // self.state = TCPState::TimeWait;
-            self.state = TCPState::Closed;
+            self.setState(TCPState::Closed);
+        }
+    }
+
+    fn setState(&mut self, state: TCPState) {
+        if self.state == state { return; }
+        debug!(quad:% = self.getQuad(), old:? = self.state, new:? = state; "State transition");
+
+        match (self.state, state) {
+            (TCPState::SynSnt, TCPState::Closed) |
+            (TCPState::SynRcvd, TCPState::Closed) |
+            (TCPState::SynRcvd, TCPState::Listen) => Counters::inc(&self.counters.attemptFails),
+            (TCPState::Estab, TCPState::Closed) |
+            (TCPState::CloseWait, TCPState::Closed) => Counters::inc(&self.counters.estabResets),
+            _ => {}
+        }
+        self.state = state;
+    }
+
+    /// Snapshot for `Interface::connections`
+    pub(crate) fn info(&self, now: Instant) -> ConnectionInfo {
+        let remaining = |deadline: Instant| deadline.saturating_duration_since(now);
+        let keepalive = match self.keepalive {
+            Some(keepalive) if self.isEstablished() => Some(
+                if self.probesSent == 0 { remaining(self.lastRecv + keepalive.idle) }
+                else { remaining(self.lastProbe + keepalive.interval) }
+            ),
+            _ => None
+        };
+        let userTimeout = match self.userTimeout {
+            Some(timeout) if self.hasUnacked() => Some(remaining(self.lastAckProgress + timeout)),
+            _ => None
+        };
+
+        ConnectionInfo {
+            quad: self.getQuad(),
+            state: self.state,
+            recvQueue: self.incoming.len(),
+            sendQueue: self.outgoing.len(),
+            send: SendSequenceInfo { una: self.send.una, nxt: self.send.nxt, wnd: self.send.wnd, iss: self.send.iss },
+            recv: RecvSequenceInfo { nxt: self.recv.nxt, wnd: self.recv.wnd, irs: self.recv.irs },
+            timers: TimerInfo {
+                idle: now.saturating_duration_since(self.lastRecv),
+                keepalive,
+                userTimeout,
+            },
         }
     }
 
+    /// Connection counts towards `currEstab` of the interface
+    pub(crate) fn isEstablished(&self) -> bool {
+        self.state == TCPState::Estab || self.state == TCPState::CloseWait
+    }
+
     fn addWrapping(num: u32, add: usize) -> u32{
         ((num as usize + add) % (1usize << 32)) as u32
     }
 
-    fn verifyPacket(&self, tcph: &TCPHeader, segLength: u32) -> bool{
+    fn verifyPacket(&self, tcph: &TcpSegment<&[u8]>, segLength: u32) -> bool{
         // ===> Check 1: valid acknowledgement
         //          send.una < ack <= send.nxt
-        let ack = tcph.acknowledgementNumber;
+        let ack = tcph.acknowledgementNumber();
         if !(ack == self.send.nxt || Connection::checkBetween(self.send.una, ack, self.send.nxt)) {return false;}
 
         // ==> Check 2: valid sequence number
         //          recv.nxt <= seq < recv.nxt + recv.wnd
         //          recv.nxt <= seq + len - 1 < recv.nxt + recv.wnd
-        let seq = tcph.sequenceNumber;
+        let seq = tcph.sequenceNumber();
         let c1 = self.recv.nxt == seq || Connection::checkBetween(self.recv.nxt, seq, self.recv.nxt + self.recv.wnd as u32);
         let c2 = self.recv.nxt == seq + segLength - 1 || Connection::checkBetween(self.recv.nxt, seq + segLength - 1, self.recv.nxt + self.recv.wnd as u32);
         if segLength == 0 {
@@ -341,22 +486,62 @@ impl Connection{
     }
 
     /// This returns (read, write) indicating whether conditional variables should be notified
-    pub fn onPacket(&mut self, tcph: TCPHeader, buff: &mut [u8], dataStart: usize, nic: &mut VNC) -> (bool, bool, bool){
-        // println!("Recieved {} bytes.", buff.len() - dataStart);
-        // println!("{:02X?}\n", &buff[..]);
-
-        println!("State: {:?}", self.state);
+    pub fn onPacket(&mut self, tcph: &TcpSegment<&[u8]>, buff: &mut [u8], nic: &mut Nic) -> (bool, bool, bool){
+        let now = self.clock.now();
+        self.stats.segmentsIn += 1;
+
+        if tcph.rst() && self.state != TCPState::Listen {
+            // Reset is valid only if it falls in receive window. Never answer it with a reset.
+            let seq = tcph.sequenceNumber();
+            if seq == self.recv.nxt || Connection::checkBetween(self.recv.nxt, seq, self.recv.nxt.wrapping_add(self.recv.wnd as u32)) {
+                debug!(quad:% = self.getQuad(); "Connection reset by peer");
+                Counters::inc(&self.counters.inRsts);
+                self.setState(TCPState::Closed);
+                self.error = Some(TcpError::ConnectionReset);
+                return (true, true, true);
+            }
+            debug!(reason = "reset out of window", quad:% = self.getQuad(), seq = seq; "Packet dropped");
+            self.counters.dropped(DropReason::ResetOutOfWindow);
+            return (false, false, false);
+        }
 
-        if !(self.state == TCPState::Listen || self.verifyPacket(&tcph, (buff.len() - dataStart) as u32)) {
+        if !(self.state == TCPState::Listen || self.verifyPacket(tcph, tcph.payload().len() as u32)) {
+            debug!(reason = "unacceptable segment", quad:% = self.getQuad(), state:? = self.state,
+                   seq = tcph.sequenceNumber(), ack = tcph.acknowledgementNumber(); "Packet dropped");
+            self.counters.dropped(DropReason::Unacceptable);
             self.handleReset(buff, tcph, nic);
+            self.setState(TCPState::Closed);
             return (false, false, true);
         }
 
+        // Any acceptable segment from the other side proves it is still alive
+        self.lastRecv = now;
+        self.probesSent = 0;
+
+        // send.una < ack <= send.nxt acknowledges new data
+        let ack = tcph.acknowledgementNumber();
+        if tcph.ack() && (ack == self.send.nxt || Connection::checkBetween(self.send.una, ack, self.send.nxt)) && ack != self.send.una {
+            let advance = ack.wrapping_sub(self.send.una) as u64;
+            // SYN and FIN occupy sequence space too, count only data
+            let unacked = self.stats.bytesSent - self.stats.bytesAcked;
+            self.stats.bytesAcked += min(advance, unacked);
+            if let Some((seq, sentAt)) = self.rttProbe {
+                if ack == seq || Connection::checkBetween(seq, ack, self.send.nxt.wrapping_add(1)) {
+                    self.stats.sampleRtt(now.duration_since(sentAt));
+                    self.rttProbe = None;
+                }
+            }
+
+            self.send.una = ack;
+            self.lastAckProgress = now;
+        }
+
+        let (received, finReceived, wasWritable) = (self.incoming.len(), self.finReceived, self.isWritable());
         match self.state {
             TCPState::Listen    => self.handleListen(buff, nic),
             TCPState::SynRcvd   => self.handleSynRcvd(buff, tcph, nic),
             TCPState::SynSnt    => {},
-            TCPState::Estab     => self.handleEstab(buff, tcph, dataStart, nic),
+            TCPState::Estab     => self.handleEstab(buff, tcph, nic),
             TCPState::FinWait1  => self.handleFinWait1(buff, tcph, nic),
             TCPState::FinWait2  => self.handleFinWait2(buff, tcph, nic),
             TCPState::Closing   => {},
@@ -366,27 +551,149 @@ impl Connection{
         };
 
         if self.state == TCPState::Closed {return (false, false, true);}
-        if self.state == TCPState::Estab {return (!self.incoming.is_empty(), true, false);}
-        return (false, false, false);
+        // Report only what this packet changed
+        let read = self.incoming.len() > received || (self.finReceived && !finReceived);
+        (read, !wasWritable && self.isWritable(), false)
+    }
+
+    /// `write` can queue data without waiting
+    pub(crate) fn isWritable(&self) -> bool {
+        self.outgoing.len() < OUTGOING_BUFFER_LIMIT
+    }
+
+    /// Something we sent is not acknowledged yet
+    fn hasUnacked(&self) -> bool {
+        self.send.una != self.send.nxt
+    }
+
+    /// Take `len` sequence numbers for a segment being sent.
+    /// Starts a fresh user timeout period if everything sent so far was acknowledged.
+    fn advanceSendNext(&mut self, len: usize) {
+        if !self.hasUnacked() {
+            self.lastAckProgress = self.clock.now();
+        }
+        self.send.nxt = Self::addWrapping(self.send.nxt, len);
+    }
+
+    /// Called periodically by the interface timer.
+    /// Returns (read, write, delete) same as `onPacket`.
+    pub fn onTick(&mut self, now: Instant, buff: &mut [u8], nic: &mut Nic) -> (bool, bool, bool) {
+        if let Some(userTimeout) = self.userTimeout {
+            if self.hasUnacked() && now.duration_since(self.lastAckProgress) >= userTimeout {
+                // Sent segments are waiting for an ACK since too long
+                self.abort(buff, nic, TcpError::TimedOut);
+                return (true, true, true);
+            }
+        }
+
+        let keepalive = match self.keepalive {
+            Some(keepalive) => keepalive,
+            None => return (false, false, false)
+        };
+        if !(self.state == TCPState::Estab || self.state == TCPState::CloseWait) {
+            return (false, false, false);
+        }
+        if now.duration_since(self.lastRecv) < keepalive.idle {
+            return (false, false, false);
+        }
+
+        if self.probesSent > 0 && now.duration_since(self.lastProbe) < keepalive.interval {
+            return (false, false, false);
+        }
+
+        if self.probesSent >= keepalive.probes {
+            // Other side did not answer any probe. Give up on this connection.
+            self.abort(buff, nic, TcpError::ConnectionAborted);
+            return (true, true, true);
+        }
+
+        debug!(quad:% = self.getQuad(), probe = self.probesSent + 1; "Sending keepalive probe");
+        self.sendKeepalive(buff, nic);
+        self.lastProbe = now;
+        self.probesSent += 1;
+        (false, false, false)
+    }
+
+    /// Copy bytes from `incoming` buffer to `buf`. Returns number of bytes copied.
+    pub(crate) fn readIncoming(&mut self, buf: &mut [u8]) -> usize {
+        let (head, tail) = self.incoming.as_slices();
+        let hlen = min(buf.len(), head.len());
+        buf[..hlen].copy_from_slice(&head[..hlen]);
+        let tlen = min(buf.len() - hlen, tail.len());
+        let len = hlen + tlen;
+        buf[hlen..len].copy_from_slice(&tail[..tlen]);
+        drop(self.incoming.drain(..len));
+        len
+    }
+
+    /// Copy as much of `buf` to `outgoing` as buffer limit allows. Returns number of bytes copied.
+    pub(crate) fn writeOutgoing(&mut self, buf: &[u8]) -> usize {
+        let len = min(buf.len(), OUTGOING_BUFFER_LIMIT.saturating_sub(self.outgoing.len()));
+        self.outgoing.extend(buf[..len].iter());
+        len
     }
 
-    fn write(&mut self, nic: &mut VNC, buff: &mut [u8], data: &[u8]) {
+    /// Keepalive probe is an ACK with sequence number one less than `send.nxt`.
+    /// It carries no new data but forces other side to respond with an ACK.
+    fn sendKeepalive(&mut self, buff: &mut [u8], nic: &mut Nic) {
+        self.tcph.ack = true;
+        self.tcph.sequenceNumber = self.send.nxt.wrapping_sub(1);
+        self.tcph.acknowledgementNumber = self.recv.nxt;
+        self.write(nic, buff, &[]);
+
+        // Reset Control bits
+        self.tcph.ack = false;
+    }
+
+    /// Send a reset and move to `Closed`. Any further read/write returns `error`.
+    pub fn abort(&mut self, buff: &mut [u8], nic: &mut Nic, error: TcpError) {
+        debug!(quad:% = self.getQuad(), reason:% = error; "Aborting connection");
+        self.tcph.rst = true;
+        self.tcph.sequenceNumber = self.send.nxt;
+        self.write(nic, buff, &[]);
+        self.tcph.rst = false;
+
+        self.setState(TCPState::Closed);
+        self.error = Some(error);
+    }
+
+    /// Move to `Closed` without telling the other side, e.g. because the device failed.
+    /// Any further read/write returns `error`.
+    pub fn fail(&mut self, error: TcpError) {
+        debug!(quad:% = self.getQuad(), reason:% = error; "Connection failed");
+        self.setState(TCPState::Closed);
+        self.error.get_or_insert(error);
+    }
+
+    fn write(&mut self, nic: &mut Nic, buff: &mut [u8], data: &[u8]) {
+        self.iph.totalLength = (self.iph.size() + self.tcph.size() + data.len()) as u16;
+        // Only non atomic datagrams need a unique identification (RFC 6864)
+        self.iph.identification = if self.iph.dontFragment { 0 } else {
+            nic.identifications.next(self.iph.sourceIP, self.iph.destinationIP, self.iph.protocol)
+        };
         self.tcph.calcChecksum(self.iph.sourceIP, self.iph.destinationIP, data);
         self.iph.serialize(&mut buff[..]);
         self.tcph.serialize(&mut buff[self.iph.size()..]);
         let hsize = self.iph.size() + self.tcph.size();
         let size = hsize + data.len();
         buff[hsize..size].copy_from_slice(data);
-        // println!("Send {} bytes.\n{:02X?}\n", data.len(), &buff[..]);
+        Counters::inc(&self.counters.outSegs);
+        if self.tcph.rst {
+            Counters::inc(&self.counters.outRsts);
+        }
+        self.stats.segmentsOut += 1;
+        self.stats.bytesSent += data.len() as u64;
+        trace!(quad:% = self.getQuad(), seq = self.tcph.sequenceNumber, ack = self.tcph.acknowledgementNumber,
+               flags:% = self.tcph.flags(), window = self.tcph.window, len = data.len(); "Packet out");
         nic.send(&buff[..size]);
     }
 
-    pub fn sendFin(&mut self, nic: &mut VNC, buff: &mut [u8]){
+    pub fn sendFin(&mut self, nic: &mut Nic, buff: &mut [u8]){
         self.tcph.fin = true;
         self.tcph.ack = true;
-        self.send.nxt = Self::addWrapping(self.send.nxt, 1);
+        self.advanceSendNext(1);
         self.write(nic, buff, &[]);
-        self.state = TCPState::FinWait1;
+        self.setState(TCPState::FinWait1);
     }
 
     pub fn push(){
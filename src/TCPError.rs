@@ -0,0 +1,110 @@
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+/// ================================================
+///                     TcpError
+/// ================================================
+/// Every failure of the stack. Converts into `io::Error` (keeping itself as the
+/// inner error) so it can be recovered with `io::Error::get_ref` + `downcast_ref`.
+#[derive(Debug)]
+pub enum TcpError {
+    /// Packet is too short or some header field is out of range
+    MalformedPacket(&'static str),
+    ChecksumFailure,
+    /// Other side sent a reset
+    ConnectionReset,
+    /// Connection was torn down locally (keepalive failed, `abort` etc.)
+    ConnectionAborted,
+    TimedOut,
+    /// Connection already ended, e.g. through the FIN exchange
+    NotConnected,
+    /// Non-blocking operation or a timed out read/write could not make progress
+    WouldBlock,
+    AddressInUse,
+    InvalidInput(&'static str),
+    /// Failure in the underlying network device
+    Device(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, TcpError>;
+
+impl TcpError {
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            TcpError::MalformedPacket(_)    => io::ErrorKind::InvalidData,
+            TcpError::ChecksumFailure       => io::ErrorKind::InvalidData,
+            TcpError::ConnectionReset       => io::ErrorKind::ConnectionReset,
+            TcpError::ConnectionAborted     => io::ErrorKind::ConnectionAborted,
+            TcpError::TimedOut              => io::ErrorKind::TimedOut,
+            TcpError::NotConnected          => io::ErrorKind::NotConnected,
+            TcpError::WouldBlock            => io::ErrorKind::WouldBlock,
+            TcpError::AddressInUse          => io::ErrorKind::AddrInUse,
+            TcpError::InvalidInput(_)       => io::ErrorKind::InvalidInput,
+            TcpError::Device(error)         => error.kind(),
+        }
+    }
+}
+
+impl Display for TcpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpError::MalformedPacket(reason)   => write!(f, "Malformed packet: {}", reason),
+            TcpError::ChecksumFailure           => write!(f, "Checksum verification failed"),
+            TcpError::ConnectionReset           => write!(f, "Connection reset by peer"),
+            TcpError::ConnectionAborted         => write!(f, "Connection aborted"),
+            TcpError::TimedOut                  => write!(f, "Operation timed out"),
+            TcpError::NotConnected              => write!(f, "Connection is closed"),
+            TcpError::WouldBlock                => write!(f, "Operation would block"),
+            TcpError::AddressInUse              => write!(f, "Port already in use by some other application"),
+            TcpError::InvalidInput(reason)      => write!(f, "Invalid input: {}", reason),
+            TcpError::Device(error)             => write!(f, "Device error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for TcpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TcpError::Device(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+/// Connections keep their error around to report it on every later call
+impl Clone for TcpError {
+    fn clone(&self) -> Self {
+        match self {
+            TcpError::MalformedPacket(reason)   => TcpError::MalformedPacket(reason),
+            TcpError::ChecksumFailure           => TcpError::ChecksumFailure,
+            TcpError::ConnectionReset           => TcpError::ConnectionReset,
+            TcpError::ConnectionAborted         => TcpError::ConnectionAborted,
+            TcpError::TimedOut                  => TcpError::TimedOut,
+            TcpError::NotConnected              => TcpError::NotConnected,
+            TcpError::WouldBlock                => TcpError::WouldBlock,
+            TcpError::AddressInUse              => TcpError::AddressInUse,
+            TcpError::InvalidInput(reason)      => TcpError::InvalidInput(reason),
+            TcpError::Device(error)             => TcpError::Device(io::Error::new(error.kind(), error.to_string())),
+        }
+    }
+}
+
+impl From<io::Error> for TcpError {
+    fn from(error: io::Error) -> Self {
+        // Unwrap errors which came from the stack itself
+        if error.get_ref().is_some_and(|inner| inner.is::<TcpError>()) {
+            let inner = error.into_inner().unwrap();
+            return *inner.downcast::<TcpError>().unwrap();
+        }
+        TcpError::Device(error)
+    }
+}
+
+impl From<TcpError> for io::Error {
+    fn from(error: TcpError) -> Self {
+        match error {
+            TcpError::Device(error) => error,
+            error => io::Error::new(error.kind(), error)
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// ================================================
+///                       Clock
+/// ================================================
+/// Time source of an interface, its connections and capture sink.
+/// Simulated interfaces share a virtual clock which only moves when the `Simulation` advances it.
+#[derive(Debug, Clone)]
+pub(crate) enum Clock {
+    System,
+    Virtual(Arc<VirtualClock>),
+}
+
+impl Clock {
+    pub(crate) fn now(&self) -> Instant {
+        match self {
+            Clock::System => Instant::now(),
+            Clock::Virtual(clock) => clock.now(),
+        }
+    }
+
+    /// Timestamp for captured packets. Virtual time starts at the unix epoch so captures are reproducible.
+    pub(crate) fn wallTime(&self) -> SystemTime {
+        match self {
+            Clock::System => SystemTime::now(),
+            Clock::Virtual(clock) => UNIX_EPOCH + clock.elapsed(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct VirtualClock {
+    base    : Instant,
+    elapsed : Mutex<Duration>,
+}
+
+impl VirtualClock {
+    pub(crate) fn new() -> Self {
+        VirtualClock { base: Instant::now(), elapsed: Mutex::new(Duration::from_secs(0)) }
+    }
+
+    pub(crate) fn now(&self) -> Instant {
+        self.base + self.elapsed()
+    }
+
+    /// Virtual time since the clock was created
+    pub(crate) fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    /// Move clock forward to `elapsed`. Time never goes back.
+    pub(crate) fn advanceTo(&self, elapsed: Duration) {
+        let mut current = self.elapsed.lock().unwrap();
+        if elapsed > *current {
+            *current = elapsed;
+        }
+    }
+}
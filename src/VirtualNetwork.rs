@@ -1,31 +1,79 @@
+use std::fmt::Debug;
 use std::io::{self, Read, Write};
 use std::fs::{File, OpenOptions};
 use std::process;
+use std::sync::{Arc, Mutex};
+use crate::TCPError::{TcpError, Result};
+use crate::Capture::{Capture, Direction};
+use crate::Fragmentation::fragment;
+use crate::Identification::Identifications;
+use crate::Parser::Ipv4Packet;
+use crate::Stats::Counters;
+use log::{debug, warn};
 
+/// ================================================
+///                      Device
+/// ================================================
+/// Backend carrying raw IPv4 packets for an `Interface`.
+/// `VNC` is the TUN device, `PcapReplay` plays back a recorded session.
+pub trait Device: Debug + Send + 'static {
+    /// Blocks until a packet arrives. Fails with `UnexpectedEof` once no more packets will arrive.
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize>;
+
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize>;
+
+    /// Like `recv`, also returning what the device knows about the packet's checksums,
+    /// e.g. from the flags of a virtio-net header. Only used when the interface is told
+    /// to trust checksum offload. The default reports every packet as unverified.
+    fn recv_offload(&self, buf: &mut [u8]) -> io::Result<(usize, RxChecksum)> {
+        Ok((self.recv(buf)?, RxChecksum::Unverified))
+    }
+
+    /// Returns another handle to the same device.
+    /// Useful to send packets from a thread other than the one blocked in `recv`.
+    fn try_clone(&self) -> io::Result<Box<dyn Device>>;
+
+    /// Largest IP packet `send` accepts. Larger packets are fragmented before
+    /// reaching the device if their DF flag is clear, dropped otherwise.
+    fn mtu(&self) -> usize {
+        1500
+    }
+}
+
+/// Checksum state of a received packet as reported by the device
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RxChecksum {
+    /// Nothing is known, checksums are verified in software
+    Unverified,
+    /// Device verified the checksums (`VIRTIO_NET_HDR_F_DATA_VALID`)
+    Valid,
+    /// Sender left the checksum to be filled in by hardware, so it only covers
+    /// the pseudo header (`VIRTIO_NET_HDR_F_NEEDS_CSUM`). Seen with local traffic.
+    Partial,
+}
+
+/// ================================================
+///                        VNC
+/// ================================================
+/// TUN device
 #[derive(Debug)]
 pub struct VNC{
     fd: File
 }
 
 impl VNC {
-    pub fn new(ifname: &str, sourceIP: &str, destIP: &str) -> io::Result<Self> {
-        let obj = Self::with_options(&("/dev/".to_owned() + ifname), true);
-        if let Ok(_) = &obj {
-            let output = process::Command::new("sudo")
-                .arg("ifconfig")
-                .arg(ifname)
-                .arg(sourceIP)
-                .arg(destIP).output().expect("Ifconfig Failed");
+    pub fn new(ifname: &str, sourceIP: &str, destIP: &str) -> Result<Self> {
+        let obj = Self::with_options(&("/dev/".to_owned() + ifname), true)?;
+        let output = process::Command::new("sudo")
+            .arg("ifconfig")
+            .arg(ifname)
+            .arg(sourceIP)
+            .arg(destIP).output()?;
+        if !output.status.success() {
+            let reason = format!("Ifconfig Failed: {}", String::from_utf8_lossy(&output.stderr));
+            return Err(TcpError::Device(io::Error::other(reason)));
         }
-        obj
-    }
-
-    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
-        (&self.fd).read(buf)
-    }
-
-    pub fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
-        (&self.fd).write(buf)
+        Ok(obj)
     }
 
     fn with_options(ifname: &str, packet_info: bool) -> io::Result<Self> {
@@ -37,3 +85,102 @@ impl VNC {
         Ok(VNC { fd })
     }
 }
+
+impl Device for VNC {
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        (&self.fd).read(buf)
+    }
+
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&self.fd).write(buf)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Device>> {
+        Ok(Box::new(VNC { fd: self.fd.try_clone()? }))
+    }
+}
+
+/// ================================================
+///                        Nic
+/// ================================================
+/// Handle to the device used by the interface threads.
+/// Every packet going through it is also written to the capture sink, if one is set.
+#[derive(Debug)]
+pub(crate) struct Nic {
+    device  : Box<dyn Device>,
+    capture : Arc<Mutex<Option<Capture>>>,
+    /// Counts fragmentation of outgoing packets
+    pub(crate) counters : Arc<Counters>,
+    /// Identification values of outgoing datagrams
+    pub(crate) identifications : Arc<Identifications>,
+}
+
+impl Nic {
+    /// `counters` and `identifications` are the interface's, shared by every clone
+    pub(crate) fn new(device: Box<dyn Device>, counters: Arc<Counters>, identifications: Identifications) -> Self {
+        Nic { device, capture: Arc::default(), counters, identifications: Arc::new(identifications) }
+    }
+
+    pub(crate) fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, RxChecksum)> {
+        let (len, checksum) = self.device.recv_offload(buf)?;
+        self.record(Direction::In, &buf[..len]);
+        Ok((len, checksum))
+    }
+
+    /// Send one IP packet, as fragments if it is larger than the device MTU
+    pub(crate) fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mtu = self.device.mtu();
+        if buf.len() <= mtu {
+            self.record(Direction::Out, buf);
+            return self.device.send(buf);
+        }
+
+        let packet = Ipv4Packet::new(buf)?;
+        let fragments = if packet.dontFragment() { None } else { fragment(&packet, mtu) };
+        let fragments = match fragments {
+            Some(fragments) => fragments,
+            None => {
+                debug!(len = buf.len(), mtu = mtu, dontFragment = packet.dontFragment(); "Packet larger than MTU dropped");
+                Counters::inc(&self.counters.fragFails);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Packet larger than MTU cannot be fragmented"));
+            }
+        };
+        Counters::inc(&self.counters.fragOKs);
+        for fragment in &fragments {
+            Counters::inc(&self.counters.fragCreates);
+            self.record(Direction::Out, fragment);
+            self.device.send(fragment)?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Clones share the same capture sink, counters and identifications
+    pub(crate) fn try_clone(&self) -> io::Result<Self> {
+        Ok(Nic {
+            device: self.device.try_clone()?,
+            capture: self.capture.clone(),
+            counters: self.counters.clone(),
+            identifications: self.identifications.clone(),
+        })
+    }
+
+    /// Replace the capture sink. The old one is flushed before being dropped.
+    pub(crate) fn setCapture(&self, capture: Option<Capture>) -> io::Result<()> {
+        let old = std::mem::replace(&mut *self.capture.lock().unwrap(), capture);
+        match old {
+            Some(mut old) => old.flush(),
+            None => Ok(())
+        }
+    }
+
+    /// A failing sink is dropped so that capture never breaks the data path
+    pub(crate) fn record(&self, direction: Direction, packet: &[u8]) {
+        let mut capture = self.capture.lock().unwrap();
+        if let Some(sink) = capture.as_mut() {
+            if let Err(error) = sink.record(direction, packet) {
+                warn!(error:% = error; "Packet capture failed, stopping capture");
+                *capture = None;
+            }
+        }
+    }
+}
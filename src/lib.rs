@@ -0,0 +1,954 @@
+#![allow(non_snake_case, unused_variables, unused_imports, unreachable_code, dead_code, unused_must_use, unused_doc_comments)]
+#![allow(clippy::needless_return, clippy::upper_case_acronyms)]
+
+//! User space TCP stack running over a TUN device.
+//!
+//! Create an `Interface`, `bind` a `TCPListener` on it and `accept` `TCPStream`s.
+//! Streams implement `std::io::{Read, Write}`, can be polled with a `Poller`
+//! or wrapped in `AsyncTCPStream` for use with async code.
+
+mod VirtualNetwork;
+pub mod Parser;
+mod TCPConnection;
+mod queue;
+mod Poll;
+mod AsyncIO;
+mod TCPError;
+mod EphemeralPorts;
+mod Stats;
+mod Netstat;
+mod Capture;
+mod Replay;
+mod Time;
+mod Random;
+mod Simulator;
+mod Impair;
+mod Conformance;
+mod Reassembly;
+mod Fragmentation;
+mod Identification;
+
+use VirtualNetwork::{VNC, Nic};
+pub use VirtualNetwork::{Device, RxChecksum};
+use Parser::*;
+use TCPConnection::*;
+pub use Parser::IPAddress;
+pub use TCPConnection::{TCPState, Keepalive};
+pub use TCPError::{TcpError, Result};
+pub use Poll::{Poller, Token, Interest, Event, Events};
+use Poll::Registration;
+use EphemeralPorts::PortAllocator;
+use Stats::{Counters, DropReason};
+pub use Stats::{InterfaceStats, DropStats, ConnectionStats};
+pub use Netstat::{ConnectionTable, ListenerInfo, ConnectionInfo, SendSequenceInfo, RecvSequenceInfo, TimerInfo};
+pub use AsyncIO::{AsyncTCPStream, AsyncTCPListener, Accept};
+pub use Capture::CaptureFormat;
+pub use Replay::{PcapReplay, ReplayPacket};
+pub use Simulator::{Simulation, Endpoint};
+pub use Impair::{Impairment, GilbertElliott, ImpairedDevice};
+pub use Conformance::{Script, ScriptError, SCRIPT_LOCAL, SCRIPT_REMOTE, SCRIPT_REMOTE_PORT};
+use Time::Clock;
+use Reassembly::Reassembler;
+use Identification::Identifications;
+
+use std::io::{self, Read, Write};
+use std::collections::{btree_map::Entry, hash_map, BTreeMap, HashMap, VecDeque};
+use std::cmp::{Eq, min};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, MutexGuard, Condvar};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use std::task::Waker;
+use log::{trace, debug, info, error};
+
+type InterfaceHandler = Arc<Mutex<ConnectionManager>>;
+
+/// Granularity of connection timers (keepalive etc.)
+const TIMER_TICK: Duration = Duration::from_millis(100);
+
+/// Wait on `cond` until notified. If `deadline` on `clock` passes first, fails with `error`.
+/// A virtual clock only moves in ticks of the `Simulation`, which wake every waiter to check it.
+fn waitUntil<'a, T>(cond: &Condvar, guard: MutexGuard<'a, T>, deadline: Option<Instant>, clock: &Clock,
+                    error: TcpError) -> Result<MutexGuard<'a, T>> {
+    match deadline {
+        None => Ok(cond.wait(guard).unwrap()),
+        Some(deadline) => {
+            let now = clock.now();
+            if now >= deadline {
+                return Err(error);
+            }
+            Ok(cond.wait_timeout(guard, deadline - now).unwrap().0)
+        }
+    }
+}
+
+/// `std::net` rejects a zero timeout since it can't be distinguished from blocking forever
+fn checkTimeout(timeout: Option<Duration>) -> Result<()> {
+    if timeout == Some(Duration::from_secs(0)) {
+        return Err(TcpError::InvalidInput("Cannot set a 0 duration timeout"));
+    }
+    Ok(())
+}
+
+/// ================================================
+///                Connection Manager
+/// ================================================
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Quad{
+    pub src: (IPAddress, u16),  // IPAddress + Port
+    pub dst: (IPAddress, u16)   // IPAddress + Port
+}
+
+impl std::fmt::Display for Quad {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{} -> {}:{}", self.src.0, self.src.1, self.dst.0, self.dst.1)
+    }
+}
+
+struct ConnectionManager{
+    terminate       : Mutex<bool>,
+    /// Ordered so timers visit connections in the same order on every run
+    connectionMap   : Mutex<BTreeMap<Quad, Arc<Active>>>,
+    /// Listeners keyed by local address. `IPAddress::UNSPECIFIED` listens on every address.
+    pendingMap      : Mutex<HashMap<(IPAddress, u16), Arc<Pending>>>,
+    /// Source of ephemeral ports. Lock it only after `connectionMap` and `pendingMap`.
+    ports           : Mutex<PortAllocator>,
+    /// Handle used for sending packets. Lock it only after `connectionMap` and `connection`.
+    nic             : Mutex<Nic>,
+    /// Keepalive applied to newly created connections
+    keepalive       : Mutex<Option<Keepalive>>,
+    /// Skip verifying checksums the device reports as valid or partial
+    checksumOffload : Mutex<bool>,
+    /// Incomplete fragmented datagrams. Never held together with another lock.
+    reassembly      : Mutex<Reassembler>,
+    /// Set once the device fails. Listeners report it instead of waiting forever.
+    deviceError     : Mutex<Option<TcpError>>,
+    counters        : Arc<Counters>,
+    clock           : Clock,
+}
+
+impl ConnectionManager {
+    /// Counts into the counters `nic` was created with
+    fn new(nic: Nic, clock: Clock) -> Self {
+        let counters = nic.counters.clone();
+        ConnectionManager {
+            terminate: Mutex::new(false),
+            connectionMap: Mutex::default(),
+            pendingMap: Mutex::default(),
+            ports: Mutex::new(PortAllocator::new()),
+            nic: Mutex::new(nic),
+            keepalive: Mutex::new(None),
+            checksumOffload: Mutex::new(false),
+            reassembly: Mutex::new(Reassembler::new(counters.clone())),
+            deviceError: Mutex::new(None),
+            counters,
+            clock,
+        }
+    }
+
+    /// Fire timers of every connection. Called every `TIMER_TICK`.
+    fn onTick(&self, buf: &mut [u8]) {
+        let now = self.clock.now();
+        self.reassembly.lock().unwrap().expire(now);
+        // Blocked calls can't time themselves on a virtual clock, wake them to check their deadline
+        let virtualTime = matches!(self.clock, Clock::Virtual(_));
+        let mut connections = self.connectionMap.lock().unwrap();
+        connections.retain(|_, active| {
+            let mut connection = active.connection.lock().unwrap();
+            let mut nic = self.nic.lock().unwrap();
+            let (read, write, delete) = connection.onTick(now, buf, &mut nic);
+            drop(nic);
+            if delete {
+                connection.isHandled = false;
+            }
+            if virtualTime {
+                active.readCond.notify_all();
+                active.writeCond.notify_all();
+            }
+            drop(connection);
+
+            active.notify(read, write, delete);
+            !delete
+        });
+        if virtualTime {
+            for pending in self.pendingMap.lock().unwrap().values() {
+                let _queue = pending.pendingQueue.lock().unwrap();
+                pending.cond.notify_all();
+            }
+        }
+    }
+
+    /// The device failed and no more packets will arrive.
+    /// Fail every connection with `error` and wake up everyone waiting on them.
+    fn onDeviceError(&self, error: TcpError) {
+        *self.deviceError.lock().unwrap() = Some(error.clone());
+        let connections = std::mem::take(&mut *self.connectionMap.lock().unwrap());
+        for active in connections.values() {
+            let mut connection = active.connection.lock().unwrap();
+            connection.fail(error.clone());
+            connection.isHandled = false;
+            drop(connection);
+            active.notify(true, true, true);
+        }
+        for pending in self.pendingMap.lock().unwrap().values() {
+            let _queue = pending.pendingQueue.lock().unwrap();
+            pending.cond.notify_all();
+            pending.notify();
+        }
+    }
+
+    /// Handle a packet received on the interface. `buf` holds exactly one IP packet,
+    /// `checksum` is what the device knows about its checksums.
+    fn onPacket(&self, buf: &[u8], checksum: RxChecksum) {
+        let packet = match Parser::Ipv4Packet::new(buf) {
+            Ok(packet) => packet,
+            Err(error) => {
+                debug!(reason:% = error, len = buf.len(); "Packet dropped");
+                self.counters.dropped(DropReason::Malformed);
+                return;
+            }
+        };
+        if !packet.verifyChecksum() {
+            debug!(reason:% = TcpError::ChecksumFailure, len = buf.len(); "Packet dropped");
+            Counters::inc(&self.counters.checksumErrors);
+            self.counters.dropped(DropReason::Checksum);
+            return;
+        }
+        if packet.protocol() != IPProtocol::Tcp as u8 {
+            trace!(reason = "not tcp", protocol = packet.protocol(); "Packet dropped");
+            self.counters.dropped(DropReason::NotTcp);
+            return;
+        }
+        if packet.moreFragments() || packet.fragmentOffset() != 0 {
+            let datagram = self.reassembly.lock().unwrap().insert(&packet, self.clock.now());
+            if let Some(datagram) = datagram {
+                // Checksum offload covered the fragments, not the reassembled segment
+                self.onPacket(&datagram, RxChecksum::Unverified);
+            }
+            return;
+        }
+        Counters::inc(&self.counters.inSegs);
+        // Payload stops at total length, anything after is link layer padding
+        let segment = match Parser::TcpSegment::new(packet.payload()) {
+            Ok(segment) => segment,
+            Err(error) => {
+                debug!(reason:% = error, len = buf.len(); "Packet dropped");
+                Counters::inc(&self.counters.inErrs);
+                self.counters.dropped(DropReason::Malformed);
+                return;
+            }
+        };
+
+        let (sourceIP, destinationIP) = (packet.sourceIP(), packet.destinationIP());
+        let trusted = checksum != RxChecksum::Unverified && *self.checksumOffload.lock().unwrap();
+        if !trusted && !segment.verifyChecksum(sourceIP, destinationIP) {
+            debug!(reason = "bad TCP checksum", len = buf.len(); "Packet dropped");
+            Counters::inc(&self.counters.inErrs);
+            Counters::inc(&self.counters.checksumErrors);
+            self.counters.dropped(DropReason::Checksum);
+            return;
+        }
+
+        let key = Quad{
+            src: (sourceIP, segment.sourcePort()),
+            dst: (destinationIP, segment.destinationPort())
+        };
+        trace!(quad:% = key, seq = segment.sequenceNumber(), ack = segment.acknowledgementNumber(),
+               flags:% = segment.flags(), window = segment.window(), len = segment.payload().len(); "Packet in");
+
+        // Replies are built here, the received packet is only borrowed
+        let mut reply = [0u8; 1500];
+        let mut connections = self.connectionMap.lock().unwrap();
+        let entry = connections.entry(key);
+
+        match entry {
+            Entry::Vacant(entry) => {
+                let pendingMap = self.pendingMap.lock().unwrap();
+                // If someone is listening then only open the connection
+                let port = segment.destinationPort();
+                let pendingConnections = match pendingMap.get(&(destinationIP, port)) {
+                    Some(pendingConnections) => Some(pendingConnections),
+                    None => pendingMap.get(&(IPAddress::UNSPECIFIED, port))
+                };
+                if pendingConnections.is_none() {
+                    debug!(reason = "no listener", quad:% = key; "Packet dropped");
+                    self.counters.dropped(DropReason::NoListener);
+                }
+                if let Some(pendingConnections) = pendingConnections {
+                    let connection = Connection::new(&packet, &segment, true, self.counters.clone(), self.clock.clone());
+                    if let Err(error) = &connection {
+                        debug!(reason:% = error, quad:% = key; "Packet dropped");
+                        self.counters.dropped(DropReason::NotSyn);
+                    }
+                    if let Ok(mut connection) = connection {
+                        Counters::inc(&self.counters.passiveOpens);
+                        connection.keepalive = *self.keepalive.lock().unwrap();
+                        let mut nic = self.nic.lock().unwrap();
+                        connection.onPacket(&segment, &mut reply, &mut nic);
+                        drop(nic);
+                        let connection = Arc::new(Active::new(connection));
+
+                        entry.insert(connection.clone());
+
+                        let mut pendingQueue = pendingConnections.pendingQueue.lock().unwrap();
+                        pendingQueue.push_back(connection);
+                        drop(pendingQueue);
+
+                        pendingConnections.notify();
+                    }
+                }
+            },
+            Entry::Occupied(mut entry) => {
+                let mut connection = entry.get_mut().connection.lock().unwrap();
+                let mut nic = self.nic.lock().unwrap();
+                let (read, write, delete) = connection.onPacket(&segment, &mut reply, &mut nic);
+                drop(nic);
+
+                if delete {
+                    // Completed Fin exchanges/unexpected behaiour from other side
+                    connection.isHandled = false;
+                }
+                drop(connection);
+                entry.get().notify(read, write, delete);
+                if delete {
+                    connections.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Pending {
+    pendingQueue : Mutex<VecDeque<Arc<Active>>>,
+    cond         : Condvar,
+    waker        : Mutex<Option<Waker>>,
+    poll         : Mutex<Option<Registration>>,
+}
+
+impl Pending {
+    /// Wake up `accept` as we got a new connection
+    fn notify(&self) {
+        self.cond.notify_one();
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+        if let Some(registration) = &*self.poll.lock().unwrap() {
+            registration.notify(true, false, false);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Active {
+    connection : Mutex<Connection>,
+    readCond   : Condvar,
+    writeCond  : Condvar,
+    readWaker  : Mutex<Option<Waker>>,
+    writeWaker : Mutex<Option<Waker>>,
+    poll       : Mutex<Option<Registration>>,
+}
+
+impl Active {
+    fn new(connection: Connection) -> Self {
+        Active {
+            connection: Mutex::new(connection),
+            readCond: Condvar::new(),
+            writeCond: Condvar::new(),
+            readWaker: Mutex::new(None),
+            writeWaker: Mutex::new(None),
+            poll: Mutex::new(None),
+        }
+    }
+
+    /// Wake up whoever is waiting for this connection.
+    /// Call with `connection` unlocked. `delete` wakes up every blocked caller.
+    fn notify(&self, read: bool, write: bool, delete: bool) {
+        if delete {
+            self.readCond.notify_all();
+            self.writeCond.notify_all();
+        }
+        else {
+            if read { self.readCond.notify_one(); }
+            if write { self.writeCond.notify_one(); }
+        }
+
+        if read || delete {
+            if let Some(waker) = self.readWaker.lock().unwrap().take() { waker.wake(); }
+        }
+        if write || delete {
+            if let Some(waker) = self.writeWaker.lock().unwrap().take() { waker.wake(); }
+        }
+
+        if let Some(registration) = &*self.poll.lock().unwrap() {
+            registration.notify(read || delete, write || delete, delete);
+        }
+    }
+}
+
+// impl Drop for ConnectionManager {
+//     fn drop(&mut self) {
+//
+//     }
+// }
+
+/// ================================================
+///                    TCPListener
+/// ================================================
+pub struct TCPListener {
+    addr: (IPAddress, u16),
+    connectionManager: Arc<ConnectionManager>,
+    pending: Arc<Pending>,
+    terminate: bool,
+    nonblocking: bool,
+}
+
+impl TCPListener {
+    /// This function blocks current thread and wait for new connection
+    /// When a new connection arrives. It resumes and returns a TCPStream
+    /// Fails with `ConnectionAborted` once the listener stops accepting and,
+    /// in non-blocking mode, with `WouldBlock` when there is no pending connection.
+    pub fn accept(&mut self) -> io::Result<TCPStream> {
+        Ok(self.acceptUntil(None)?)
+    }
+
+    /// Same as `accept` but gives up with `TimedOut` if no connection arrives within `timeout`
+    pub fn accept_timeout(&mut self, timeout: Duration) -> io::Result<TCPStream> {
+        Ok(self.acceptUntil(Some(self.connectionManager.clock.now() + timeout))?)
+    }
+
+    /// In non-blocking mode `accept` and `accept_timeout` fail with `WouldBlock`
+    /// instead of waiting when there is no pending connection.
+    pub fn set_nonblocking(&mut self, nonblocking: bool) -> io::Result<()> {
+        self.nonblocking = nonblocking;
+        Ok(())
+    }
+
+    /// Hand over an accepted connection to the user
+    fn stream(&self, connection: Arc<Active>) -> TCPStream {
+        connection.connection.lock().unwrap().isHandled = true;
+        TCPStream{
+            connectionManager: self.connectionManager.clone(),
+            connection
+        }
+    }
+
+    /// Address this listener is bound to. Port is the allocated one if bound to port `0`.
+    pub fn local_addr(&self) -> (IPAddress, u16) {
+        self.addr
+    }
+
+    /// Error of the failed device. No connection will arrive after it.
+    fn deviceError(&self) -> Option<TcpError> {
+        self.connectionManager.deviceError.lock().unwrap().clone()
+    }
+
+    fn acceptUntil(&mut self, deadline: Option<Instant>) -> Result<TCPStream> {
+        let mut pendingQueue = self.pending.pendingQueue.lock().unwrap();
+        loop {
+            if self.terminate {
+                // Stop accepting new connections
+                return Err(TcpError::ConnectionAborted);
+            }
+            if let Some(error) = self.deviceError() {
+                return Err(error);
+            }
+            match pendingQueue.pop_front() {
+                Some(connection) =>  {
+                    return Ok(self.stream(connection));
+                },
+                None => {
+                    if self.nonblocking {
+                        return Err(TcpError::WouldBlock);
+                    }
+                    pendingQueue = waitUntil(&self.pending.cond, pendingQueue, deadline, &self.connectionManager.clock, TcpError::TimedOut)?;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for TCPListener {
+    fn drop(&mut self) {
+        // Stop accepting new connection
+        debug!(port = self.addr.1; "Listener dropped");
+        self.terminate = true;
+        self.pending.cond.notify_one();
+
+        // TODO: Optional: Send fin packets to all these connections
+        // let mut pendingQueue = self.pending.pendingQueue.lock().unwrap();
+        // for quad in pending {
+        //
+        // }
+
+        // TODO: Remove entry in hashmap
+        // drop(pendingQueue);
+        let mut pendingMap = self.connectionManager.pendingMap.lock().unwrap();
+        pendingMap.remove(&self.addr);
+    }
+}
+
+/// ================================================
+///                     Interface
+/// ================================================
+pub struct Interface {
+    thread: Option<std::thread::JoinHandle<()>>,
+    timer: Option<std::thread::JoinHandle<()>>,
+    connectionManager: Arc<ConnectionManager>
+}
+
+impl Interface {
+    /// Create an interface on TUN device `iface` with address `selfIP`
+    pub fn new(iface: &str, selfIP: IPAddress, otherIP: IPAddress) -> Result<Self> {
+        let nic = VNC::new(iface, &(selfIP.toString())[..], &(otherIP.toString())[..])?;
+        Interface::with_device(nic)
+    }
+
+    /// Create an interface over any packet device, e.g. a `PcapReplay`
+    pub fn with_device<D: Device>(device: D) -> Result<Self> {
+        info!(nic:? = device; "Starting NIC");
+        let nic = Nic::new(Box::new(device), Arc::default(), Identifications::new());
+        let connectionManager = Arc::new(ConnectionManager::new(nic.try_clone()?, Clock::System));
+        let thread = {
+            let connectionManager = connectionManager.clone();
+            std::thread::spawn(move || {
+                if let Err(error) = Interface::packetLoop(nic, connectionManager.clone()) {
+                    error!(reason:% = error; "Device failed");
+                    connectionManager.onDeviceError(error);
+                }
+            })
+        };
+        let timer = {
+            let connectionManager = connectionManager.clone();
+            std::thread::spawn(move || {
+                Interface::timerLoop(connectionManager);
+            })
+        };
+
+        Ok(Self{thread: Some(thread), timer: Some(timer), connectionManager})
+    }
+
+    /// Keepalive used by connections accepted from now on.
+    /// `None` (default) disables keepalive.
+    pub fn set_keepalive(&mut self, keepalive: Option<Keepalive>) {
+        *self.connectionManager.keepalive.lock().unwrap() = keepalive;
+    }
+
+    /// Trust the device's checksum offload: packets it reports as `RxChecksum::Valid`
+    /// or `RxChecksum::Partial` skip TCP checksum verification. Off by default.
+    pub fn set_checksum_offload(&mut self, trust: bool) {
+        *self.connectionManager.checksumOffload.lock().unwrap() = trust;
+    }
+
+    /// Send a raw IPv4 packet through the interface's output path. Packets larger
+    /// than the device MTU are fragmented when DF is clear, and fail otherwise.
+    pub fn send_datagram(&self, packet: &[u8]) -> Result<()> {
+        Parser::Ipv4Packet::new(packet)?;
+        self.connectionManager.nic.lock().unwrap().send(packet).map_err(TcpError::Device)?;
+        Ok(())
+    }
+
+    /// Write every packet received or sent on this interface to `writer`, e.g. a pcap file
+    /// opened in Wireshark. Replaces (and flushes) the capture already running, if any.
+    /// Only `CaptureFormat::PcapNg` records whether a packet was received or sent.
+    pub fn start_capture<W: Write + Send + 'static>(&self, writer: W, format: CaptureFormat) -> Result<()> {
+        let capture = Capture::Capture::new(Box::new(writer), format, self.connectionManager.clock.clone())?;
+        self.connectionManager.nic.lock().unwrap().setCapture(Some(capture))?;
+        Ok(())
+    }
+
+    /// Stop capturing packets and flush the capture sink
+    pub fn stop_capture(&self) -> Result<()> {
+        self.connectionManager.nic.lock().unwrap().setCapture(None)?;
+        Ok(())
+    }
+
+    /// Snapshot of counters for every connection on this interface
+    pub fn stats(&self) -> InterfaceStats {
+        let connections = self.connectionManager.connectionMap.lock().unwrap();
+        let currEstab = connections.values()
+            .filter(|active| active.connection.lock().unwrap().isEstablished())
+            .count();
+        self.connectionManager.counters.snapshot(currEstab as u64)
+    }
+
+    /// Snapshot of listeners and connections, like `ss -tan`
+    pub fn connections(&self) -> ConnectionTable {
+        let connections = self.connectionManager.connectionMap.lock().unwrap();
+        let pendingMap = self.connectionManager.pendingMap.lock().unwrap();
+        let now = self.connectionManager.clock.now();
+
+        let mut table = ConnectionTable {
+            listeners: pendingMap.iter()
+                .map(|(&addr, pending)| ListenerInfo {
+                    addr,
+                    backlog: pending.pendingQueue.lock().unwrap().len()
+                })
+                .collect(),
+            connections: connections.values()
+                .map(|active| active.connection.lock().unwrap().info(now))
+                .collect(),
+        };
+        table.listeners.sort_by_key(|listener| (listener.addr.1, listener.addr.0.bytes));
+        table.connections.sort_by_key(|connection| (connection.quad.dst.1, connection.quad.src.0.bytes, connection.quad.src.1));
+        table
+    }
+
+    /// Create a poller that can wait on streams and listeners of this interface
+    pub fn poller(&self) -> Poller {
+        Poller::new(Arc::as_ptr(&self.connectionManager) as usize)
+    }
+
+    /// This Loop runs forever and fires connection timers every `TIMER_TICK`
+    fn timerLoop(connectionManager: Arc<ConnectionManager>) {
+        let mut buf = [0u8; 1500];
+        loop {
+            sleep(TIMER_TICK);
+            {
+                let terminate = connectionManager.terminate.lock().unwrap();
+                if *terminate {
+                    return;
+                }
+            }
+            connectionManager.onTick(&mut buf);
+        }
+    }
+
+    /// This Loop runs forever and looks for any incoming packets
+    fn packetLoop(nic: Nic, connectionManager: Arc<ConnectionManager>) -> Result<()>{
+        let mut buf = [0u8; 1500];
+        loop {
+            let (bytesRead, checksum) = match nic.recv(&mut buf) {
+                Ok(received) => received,
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                    info!("Device has no more packets");
+                    return Ok(());
+                },
+                Err(error) => return Err(error.into())
+            };
+            {
+                let terminate = connectionManager.terminate.lock().unwrap();
+                if *terminate {
+                    // Stop accepting new packets and end this thread
+                    return Ok(());
+                }
+            }
+            connectionManager.onPacket(&buf[..bytesRead], checksum);
+        }
+        Ok(())
+    }
+
+    /// Start listening on `addr`. Use `IPAddress::UNSPECIFIED` to accept connections
+    /// to any local address and port `0` to pick a free ephemeral port.
+    pub fn bind(&mut self, addr: (IPAddress, u16)) -> Result<TCPListener> {
+        let connections = self.connectionManager.connectionMap.lock().unwrap();
+        let mut pendingMap = self.connectionManager.pendingMap.lock().unwrap();
+
+        let (ip, mut port) = addr;
+        if port == 0 {
+            let inUse = |port: u16| {
+                pendingMap.keys().any(|&(_, listening)| listening == port) ||
+                    connections.keys().any(|quad| quad.dst.1 == port)
+            };
+            port = self.connectionManager.ports.lock().unwrap()
+                .allocate(ip, None, inUse)
+                .ok_or(TcpError::AddressInUse)?;
+        }
+
+        // Wildcard and specific address on same port would both claim the same packets
+        let conflict = pendingMap.keys().any(|&(listeningIP, listening)| {
+            listening == port && (listeningIP == ip || listeningIP.isUnspecified() || ip.isUnspecified())
+        });
+        if conflict {
+            return Err(TcpError::AddressInUse);
+        }
+
+        // Create new pendingQueue for pending connections on this port
+        let pending = Arc::new(
+            Pending {
+                pendingQueue: Mutex::new(VecDeque::new()),
+                cond : Condvar::new(),
+                waker: Mutex::new(None),
+                poll : Mutex::new(None)
+            }
+        );
+        pendingMap.insert((ip, port), pending.clone());
+        Ok(TCPListener {
+            addr: (ip, port),
+            connectionManager: self.connectionManager.clone(),
+            pending,
+            terminate: false,
+            nonblocking: false
+        })
+    }
+}
+
+impl Drop for Interface {
+    fn drop(&mut self) {
+        let mut terminate = self.connectionManager.terminate.lock().unwrap();
+        *terminate = true;
+        drop(terminate);
+        // Join the threads running packet loop and timers. Simulated interfaces have none.
+        if let Some(timer) = self.timer.take() { timer.join(); }
+        if let Some(thread) = self.thread.take() { thread.join(); }
+    }
+}
+
+/// ================================================
+///                     TCPStream
+/// ================================================
+/// This provides interface to read and write data in a connection
+pub struct TCPStream{
+    connectionManager: Arc<ConnectionManager>,
+    connection: Arc<Active>,
+}
+
+impl std::fmt::Debug for TCPStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let quad = self.connection.connection.lock().unwrap().getQuad();
+        write!(f, "TCPStream({})", quad)
+    }
+}
+
+impl Read for TCPStream{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut connection = self.connection.connection.lock().unwrap();
+        let deadline = connection.readTimeout.map(|timeout| self.connectionManager.clock.now() + timeout);
+        loop {
+            if let Some(error) = &connection.error {
+                return Err(error.clone().into());
+            }
+            if !connection.incoming.is_empty() {
+                return Ok(connection.readIncoming(buf));
+            }
+            if connection.finReceived {
+                // End of file
+                return Ok(0);
+            }
+            if !connection.isHandled {
+                return Err(TcpError::ConnectionAborted.into());
+            }
+
+            if connection.nonblocking {
+                return Err(TcpError::WouldBlock.into());
+            }
+            connection = waitUntil(&self.connection.readCond, connection, deadline, &self.connectionManager.clock, TcpError::WouldBlock)?;
+        };
+    }
+}
+
+impl Write for TCPStream{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.raw_write(buf, true)
+    }
+
+    /// This functions blocks current thread until it successfully recieves
+    /// ACK for all bytes send on network.
+    fn flush(&mut self) -> io::Result<()> {
+        let mut connection = self.connection.connection.lock().unwrap();
+        let deadline = connection.writeTimeout.map(|timeout| self.connectionManager.clock.now() + timeout);
+        loop {
+            if let Some(error) = &connection.error {
+                return Err(error.clone().into());
+            }
+            if connection.outgoing.is_empty(){
+                return Ok(());
+            }
+            if connection.nonblocking {
+                return Err(TcpError::WouldBlock.into());
+            }
+            connection = waitUntil(&self.connection.writeCond, connection, deadline, &self.connectionManager.clock, TcpError::WouldBlock)?;
+        };
+    }
+}
+
+impl TCPStream{
+    /// Use this if you want to send large data but don't want a large buffer.
+    /// Push indicates other side that this is all you wanted to send as response.
+    pub fn raw_write(&mut self, mut buf: &[u8], push: bool) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut bytesWritten: usize = 0;
+        let mut connection = self.connection.connection.lock().unwrap();
+        let deadline = connection.writeTimeout.map(|timeout| self.connectionManager.clock.now() + timeout);
+        loop{
+            // let mut connection = self.connection.connection.lock().unwrap();
+            if let Some(error) = &connection.error {
+                return Err(error.clone().into());
+            }
+            if connection.outgoing.len() < OUTGOING_BUFFER_LIMIT{
+                let len = connection.writeOutgoing(buf);
+                buf = &buf[len..];
+                bytesWritten += len;
+                if buf.is_empty() {
+                    if push {
+                        // connection.push();
+                    }
+                    return Ok(bytesWritten);
+                }
+            }
+
+            if connection.nonblocking {
+                return if bytesWritten > 0 { Ok(bytesWritten) } else { Err(TcpError::WouldBlock.into()) };
+            }
+            connection = match waitUntil(&self.connection.writeCond, connection, deadline, &self.connectionManager.clock, TcpError::WouldBlock) {
+                Ok(connection) => connection,
+                // Report partial writes as success
+                Err(_) if bytesWritten > 0 => return Ok(bytesWritten),
+                Err(error) => return Err(error.into())
+            };
+        }
+    }
+
+    /// In non-blocking mode `read`, `write` and `flush` fail with `WouldBlock`
+    /// instead of waiting. A `write` that fits partially returns the bytes accepted.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.connection.connection.lock().unwrap().nonblocking = nonblocking;
+        Ok(())
+    }
+
+    /// Timeout for blocking `read`. `None` (default) blocks forever.
+    /// A timed out read fails with `WouldBlock` just like `std::net::TcpStream` on unix.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        checkTimeout(timeout).map_err(io::Error::from)?;
+        self.connection.connection.lock().unwrap().readTimeout = timeout;
+        Ok(())
+    }
+
+    /// Timeout for blocking `write` and `flush`. `None` (default) blocks forever.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        checkTimeout(timeout).map_err(io::Error::from)?;
+        self.connection.connection.lock().unwrap().writeTimeout = timeout;
+        Ok(())
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(self.connection.connection.lock().unwrap().readTimeout)
+    }
+
+    pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(self.connection.connection.lock().unwrap().writeTimeout)
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        self.connection.connection.lock().unwrap().stats
+    }
+
+    /// Overrides keepalive for this connection. `None` disables it.
+    pub fn set_keepalive(&self, keepalive: Option<Keepalive>) {
+        self.connection.connection.lock().unwrap().keepalive = keepalive;
+    }
+
+    pub fn keepalive(&self) -> Option<Keepalive> {
+        self.connection.connection.lock().unwrap().keepalive
+    }
+
+    /// Abort connection if a sent segment stays unacknowledged for longer than `timeout`.
+    /// Blocked and future calls then fail with `TimedOut`. `None` (default) waits forever.
+    /// The stack does not transmit written data yet, so only its SYN and FIN are timed.
+    pub fn set_user_timeout(&self, timeout: Option<Duration>) {
+        self.connection.connection.lock().unwrap().userTimeout = timeout;
+    }
+
+    pub fn user_timeout(&self) -> Option<Duration> {
+        self.connection.connection.lock().unwrap().userTimeout
+    }
+
+    /// Don't Fragment flag of packets sent on this connection (default `true`).
+    /// With DF clear, packets larger than the device MTU are fragmented.
+    pub fn set_dont_fragment(&self, dontFragment: bool) {
+        self.connection.connection.lock().unwrap().iph.dontFragment = dontFragment;
+    }
+
+    pub fn dont_fragment(&self) -> bool {
+        self.connection.connection.lock().unwrap().iph.dontFragment
+    }
+
+    /// Time To Live of packets sent on this connection (default 64), 1 to 255
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        if ttl == 0 || ttl > 255 {
+            return Err(TcpError::InvalidInput("TTL must be between 1 and 255").into());
+        }
+        self.connection.connection.lock().unwrap().iph.ttl = ttl as u8;
+        Ok(())
+    }
+
+    pub fn ttl(&self) -> u32 {
+        self.connection.connection.lock().unwrap().iph.ttl as u32
+    }
+
+    /// Differentiated Services Code Point of packets sent on this connection (RFC 2474),
+    /// 0 (default) to 63
+    pub fn set_dscp(&self, dscp: u8) -> io::Result<()> {
+        if dscp > 63 {
+            return Err(TcpError::InvalidInput("DSCP must fit in 6 bits").into());
+        }
+        self.connection.connection.lock().unwrap().iph.dscp = dscp;
+        Ok(())
+    }
+
+    pub fn dscp(&self) -> u8 {
+        self.connection.connection.lock().unwrap().iph.dscp
+    }
+
+    /// ECN codepoint of packets sent on this connection (RFC 3168), 0 (default) to 3.
+    /// The stack does not react to congestion marks, so only set ECT when the peer
+    /// and path are known to handle it.
+    pub fn set_ecn(&self, ecn: u8) -> io::Result<()> {
+        if ecn > 3 {
+            return Err(TcpError::InvalidInput("ECN must fit in 2 bits").into());
+        }
+        self.connection.connection.lock().unwrap().iph.ecn = ecn;
+        Ok(())
+    }
+
+    pub fn ecn(&self) -> u8 {
+        self.connection.connection.lock().unwrap().iph.ecn
+    }
+
+    /// Immediately reset the connection without a graceful close.
+    /// Unsent and unacknowledged data is discarded.
+    /// Fails with `NotConnected` once the connection has been closed.
+    pub fn abort(&self) -> io::Result<()> {
+        let mut connections = self.connectionManager.connectionMap.lock().unwrap();
+        let mut connection = self.connection.connection.lock().unwrap();
+        if let Some(error) = &connection.error {
+            return Err(error.clone().into());
+        }
+        if !connection.isHandled {
+            return Err(TcpError::NotConnected.into());
+        }
+
+        let mut buf = [0u8; 1500];
+        let mut nic = self.connectionManager.nic.lock().unwrap();
+        connection.abort(&mut buf, &mut nic, TcpError::ConnectionAborted);
+        drop(nic);
+
+        connection.isHandled = false;
+        connection.outgoing.clear();
+        connections.remove(&connection.getQuad());
+        drop(connection);
+
+        self.connection.notify(false, false, true);
+        Ok(())
+    }
+
+    pub fn close() {
+        // TODO: Send a fin
+        unimplemented!();
+    }
+}
+
+impl Drop for TCPStream {
+    fn drop(&mut self){
+        let connection = self.connection.connection.lock().unwrap();
+        // TODO: Send fin packets to close connection
+        // connection.sendFin();
+
+        // let mut connectionMap = self.connectionManager.connectionMap.lock().unwrap();
+        // let key = connection.getQuad();
+        // connectionMap.remove(&key);
+    }
+}
@@ -93,6 +93,6 @@ impl<T: Copy + std::fmt::Debug> Queue<T> {
                 print!("{:?} ", self.arr[i]);
             }
         }
-        print!("\n");
+        println!();
     }
 }
\ No newline at end of file
@@ -0,0 +1,140 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use common::{handshake, segment, setup, syn};
+use futures_io::{AsyncRead, AsyncWrite};
+use TCP::{AsyncTCPListener, AsyncTCPStream, Endpoint, Simulation};
+use std::future::Future;
+use std::io::ErrorKind;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+
+/// Waker counting how often it was woken, all the executor a test needs
+#[derive(Default)]
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl CountingWaker {
+    fn wakes(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+fn context() -> (Arc<CountingWaker>, Waker) {
+    let counter = Arc::new(CountingWaker::default());
+    (counter.clone(), Waker::from(counter))
+}
+
+/// Established connection from `REMOTE:40000`
+fn connect() -> (Simulation, TCP::Interface, Endpoint, AsyncTCPStream) {
+    let (mut simulation, interface, peer, mut listener) = setup(1);
+    let stream = handshake(&mut simulation, &mut listener, &peer, 40000);
+    (simulation, interface, peer, AsyncTCPStream::from(stream))
+}
+
+#[test]
+fn accept_future_is_woken_by_syn() {
+    let (mut simulation, _interface, peer, listener) = setup(1);
+    let mut listener = AsyncTCPListener::from(listener);
+    let (counter, waker) = context();
+    let mut cx = Context::from_waker(&waker);
+    let mut accept = listener.accept();
+    assert!(Pin::new(&mut accept).poll(&mut cx).is_pending());
+
+    // Connections are queued for `accept` when their SYN arrives
+    peer.send(&syn(40000, 100));
+    simulation.advance(Duration::from_millis(10));
+    assert_eq!(counter.wakes(), 1);
+    assert!(matches!(Pin::new(&mut accept).poll(&mut cx), Poll::Ready(Ok(_))));
+}
+
+fn read(stream: &mut AsyncTCPStream, cx: &mut Context<'_>) -> Poll<std::io::Result<Vec<u8>>> {
+    let mut buf = [0u8; 64];
+    Pin::new(stream).poll_read(cx, &mut buf).map(|result| result.map(|len| buf[..len].to_vec()))
+}
+
+#[test]
+fn pending_read_is_woken_by_data() {
+    let (mut simulation, _interface, peer, mut stream) = connect();
+    let (counter, waker) = context();
+    let mut cx = Context::from_waker(&waker);
+
+    assert!(read(&mut stream, &mut cx).is_pending());
+    simulation.advance(Duration::from_secs(1));
+    assert_eq!(counter.wakes(), 0);
+
+    peer.send(&segment(40000, 101, 1, "PA", b"hello"));
+    simulation.advance(Duration::from_millis(10));
+    assert_eq!(counter.wakes(), 1);
+    assert_eq!(read(&mut stream, &mut cx).map(Result::unwrap), Poll::Ready(b"hello".to_vec()));
+    assert!(read(&mut stream, &mut cx).is_pending());
+}
+
+#[test]
+fn end_of_file_reaches_pending_read() {
+    let (mut simulation, _interface, peer, mut stream) = connect();
+    let (counter, waker) = context();
+    let mut cx = Context::from_waker(&waker);
+
+    assert!(read(&mut stream, &mut cx).is_pending());
+    peer.send(&segment(40000, 101, 1, "PA", b"bye"));
+    peer.send(&segment(40000, 104, 1, "FA", &[]));
+    simulation.advance(Duration::from_millis(10));
+    assert!(counter.wakes() >= 1);
+    assert_eq!(read(&mut stream, &mut cx).map(Result::unwrap), Poll::Ready(b"bye".to_vec()));
+    assert_eq!(read(&mut stream, &mut cx).map(Result::unwrap), Poll::Ready(Vec::new()));
+}
+
+#[test]
+fn reset_reaches_pending_read_and_close() {
+    let (mut simulation, _interface, peer, mut stream) = connect();
+    let (readWakes, readWaker) = context();
+    let (closeWakes, closeWaker) = context();
+
+    // Written data is never acknowledged, so close keeps waiting
+    let mut cx = Context::from_waker(&closeWaker);
+    assert!(matches!(Pin::new(&mut stream).poll_write(&mut cx, b"data"), Poll::Ready(Ok(4))));
+    assert!(Pin::new(&mut stream).poll_close(&mut cx).is_pending());
+    assert!(read(&mut stream, &mut Context::from_waker(&readWaker)).is_pending());
+
+    peer.send(&segment(40000, 101, 1, "R", &[]));
+    simulation.advance(Duration::from_millis(10));
+    assert_eq!((readWakes.wakes(), closeWakes.wakes()), (1, 1));
+    match read(&mut stream, &mut Context::from_waker(&readWaker)) {
+        Poll::Ready(Err(error)) => assert_eq!(error.kind(), ErrorKind::ConnectionReset),
+        other => panic!("Read gave {:?}", other),
+    }
+    match Pin::new(&mut stream).poll_close(&mut cx) {
+        Poll::Ready(Err(error)) => assert_eq!(error.kind(), ErrorKind::ConnectionReset),
+        other => panic!("Close gave {:?}", other),
+    }
+}
+
+#[test]
+fn write_is_pending_while_send_buffer_is_full() {
+    let (_simulation, _interface, _peer, mut stream) = connect();
+    let (_, waker) = context();
+    let mut cx = Context::from_waker(&waker);
+
+    let data = vec![1u8; 300 * 1024];
+    assert!(matches!(Pin::new(&mut stream).poll_write(&mut cx, &data), Poll::Ready(Ok(len)) if len == 256 * 1024));
+    assert!(Pin::new(&mut stream).poll_write(&mut cx, &data).is_pending());
+    assert!(matches!(Pin::new(&mut stream).poll_write(&mut cx, &[]), Poll::Ready(Ok(0))));
+}
+
+#[test]
+fn close_without_pending_data_completes() {
+    let (_simulation, _interface, _peer, mut stream) = connect();
+    let (_, waker) = context();
+    let mut cx = Context::from_waker(&waker);
+    assert!(matches!(Pin::new(&mut stream).poll_close(&mut cx), Poll::Ready(Ok(()))));
+}
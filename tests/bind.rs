@@ -0,0 +1,65 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use common::{syn, LOCAL, REMOTE};
+use TCP::{IPAddress, Simulation, TcpError};
+use std::time::Duration;
+
+#[test]
+fn port_zero_picks_a_free_ephemeral_port() {
+    let mut simulation = Simulation::new(1);
+    let mut interface = simulation.add_interface(LOCAL).unwrap();
+
+    let first = interface.bind((LOCAL, 0)).unwrap();
+    let second = interface.bind((IPAddress::UNSPECIFIED, 0)).unwrap();
+    let (firstPort, secondPort) = (first.local_addr().1, second.local_addr().1);
+    assert!(firstPort >= 49152 && secondPort >= 49152);
+    assert_ne!(firstPort, secondPort);
+    assert_eq!(first.local_addr().0, LOCAL);
+
+    // The picked port is really bound
+    assert!(matches!(interface.bind((LOCAL, firstPort)), Err(TcpError::AddressInUse)));
+}
+
+#[test]
+fn port_zero_is_reproducible_in_simulation() {
+    let port = |seed| {
+        let mut simulation = Simulation::new(seed);
+        let mut interface = simulation.add_interface(LOCAL).unwrap();
+        let listener = interface.bind((LOCAL, 0)).unwrap();
+        listener.local_addr().1
+    };
+    assert_eq!(port(9), port(9));
+}
+
+#[test]
+fn wildcard_and_specific_address_conflict() {
+    let mut simulation = Simulation::new(1);
+    let mut interface = simulation.add_interface(LOCAL).unwrap();
+
+    let specific = interface.bind((LOCAL, 9000)).unwrap();
+    assert!(matches!(interface.bind((IPAddress::UNSPECIFIED, 9000)), Err(TcpError::AddressInUse)));
+    assert!(matches!(interface.bind((LOCAL, 9000)), Err(TcpError::AddressInUse)));
+    let _other = interface.bind((LOCAL, 9001)).unwrap();
+
+    // Port is free again once the listener is gone
+    drop(specific);
+    let wildcard = interface.bind((IPAddress::UNSPECIFIED, 9000)).unwrap();
+    assert!(matches!(interface.bind((LOCAL, 9000)), Err(TcpError::AddressInUse)));
+    drop(wildcard);
+    assert!(interface.bind((LOCAL, 9000)).is_ok());
+}
+
+#[test]
+fn wildcard_listener_accepts_for_local_address() {
+    let mut simulation = Simulation::new(1);
+    let mut interface = simulation.add_interface(LOCAL).unwrap();
+    let peer = simulation.add_endpoint(REMOTE).unwrap();
+    let mut listener = interface.bind((IPAddress::UNSPECIFIED, 9000)).unwrap();
+    listener.set_nonblocking(true).unwrap();
+
+    peer.send(&syn(40000, 100));
+    simulation.advance(Duration::from_millis(10));
+    assert!(listener.accept().is_ok());
+}
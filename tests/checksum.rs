@@ -0,0 +1,109 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use common::{handshake, segment, setup, syn};
+use TCP::{Device, ImpairedDevice, Impairment, Interface, InterfaceStats, IPAddress, RxChecksum};
+use std::io::{self, Read};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[test]
+fn corrupted_payload_is_dropped() {
+    let (mut simulation, interface, peer, mut listener) = setup(5);
+    let mut stream = handshake(&mut simulation, &mut listener, &peer, 40000);
+    stream.set_nonblocking(true).unwrap();
+
+    // Headers intact, last payload byte flipped
+    let mut corrupted = segment(40000, 101, 1, "PA", b"hello");
+    *corrupted.last_mut().unwrap() ^= 0x20;
+    peer.send(&corrupted);
+    simulation.advance(Duration::from_millis(10));
+
+    assert!(peer.recv().is_none());
+    assert!(stream.read(&mut [0u8; 10]).is_err());
+    let stats = interface.stats();
+    assert_eq!((stats.checksumErrors, stats.inErrs, stats.drops.checksum), (1, 1, 1));
+
+    peer.send(&segment(40000, 101, 1, "PA", b"hello"));
+    simulation.advance(Duration::from_millis(10));
+    assert_eq!(stream.read(&mut [0u8; 10]).unwrap(), 5);
+}
+
+/// Delivers packets pushed by the test, reporting every one as checksummed by hardware.
+/// Runs out of packets once `packets` is set to `None`.
+#[derive(Debug, Clone, Default)]
+struct OffloadDevice {
+    packets: Arc<Mutex<Option<VecDeque<Vec<u8>>>>>,
+}
+
+impl Device for OffloadDevice {
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.packets.lock().unwrap().as_mut() {
+                None => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Some(packets) => if let Some(packet) = packets.pop_front() {
+                    buf[..packet.len()].copy_from_slice(&packet);
+                    return Ok(packet.len());
+                }
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn recv_offload(&self, buf: &mut [u8]) -> io::Result<(usize, RxChecksum)> {
+        Ok((self.recv(buf)?, RxChecksum::Partial))
+    }
+
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Device>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+/// SYN with a checksum covering only the pseudo header, received with or without trusting offload,
+/// optionally through an `ImpairedDevice` wrapping the offloading device
+fn partialChecksumSyn(trust: bool, impaired: bool) -> InterfaceStats {
+    let device = OffloadDevice { packets: Arc::new(Mutex::new(Some(VecDeque::new()))) };
+    let mut interface = if impaired {
+        let impaired = ImpairedDevice::new(device.clone(), Impairment::default(), Impairment::default(), 1).unwrap();
+        Interface::with_device(impaired).unwrap()
+    }
+    else {
+        Interface::with_device(device.clone()).unwrap()
+    };
+    interface.set_checksum_offload(trust);
+    let _listener = interface.bind((IPAddress::UNSPECIFIED, 9000)).unwrap();
+
+    let mut packet = syn(40000, 100);
+    packet[36..38].copy_from_slice(&[0x12, 0x34]);
+    device.packets.lock().unwrap().as_mut().unwrap().push_back(packet);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while interface.stats().inSegs == 0 {
+        assert!(Instant::now() < deadline, "Packet not processed");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    // Ends the receive thread, so the interface can be dropped
+    *device.packets.lock().unwrap() = None;
+    interface.stats()
+}
+
+#[test]
+fn offloaded_checksum_is_trusted_only_when_enabled() {
+    let stats = partialChecksumSyn(false, false);
+    assert_eq!((stats.checksumErrors, stats.passiveOpens), (1, 0));
+
+    let stats = partialChecksumSyn(true, false);
+    assert_eq!((stats.checksumErrors, stats.passiveOpens), (0, 1));
+}
+
+#[test]
+fn impaired_device_keeps_offloaded_checksum_state() {
+    let stats = partialChecksumSyn(true, true);
+    assert_eq!((stats.checksumErrors, stats.passiveOpens), (0, 1));
+}
@@ -0,0 +1,97 @@
+#![allow(dead_code)]
+
+use TCP::{Endpoint, Interface, IPAddress, Simulation, TCPListener, TCPStream};
+use TCP::Parser::{IPHeader, IPProtocol, Ipv4Packet, TCPHeader};
+use std::time::Duration;
+
+pub const LOCAL: IPAddress = IPAddress { bytes: [10, 0, 0, 1] };
+pub const REMOTE: IPAddress = IPAddress { bytes: [10, 0, 0, 2] };
+
+/// IP packet from `REMOTE:port` to `LOCAL:9000` with control bits given as letters, e.g. "SA"
+pub fn segment(port: u16, seq: u32, ack: u32, flags: &str, data: &[u8]) -> Vec<u8> {
+    segmentBetween(REMOTE, LOCAL, port, seq, ack, flags, data)
+}
+
+/// Same as `segment` from `source:port` to `destination:9000`
+pub fn segmentBetween(source: IPAddress, destination: IPAddress, port: u16, seq: u32, ack: u32, flags: &str, data: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0u8; 40 + data.len()];
+    let mut tcph = TCPHeader::new(port, 9000, seq, 1024);
+    tcph.acknowledgementNumber = ack;
+    tcph.syn = flags.contains('S');
+    tcph.ack = flags.contains('A');
+    tcph.fin = flags.contains('F');
+    tcph.rst = flags.contains('R');
+    tcph.psh = flags.contains('P');
+    tcph.calcChecksum(source, destination, data);
+    IPHeader::new(source, destination, IPProtocol::Tcp, 64, 20 + data.len() as u16).serialize(&mut buf);
+    tcph.serialize(&mut buf[20..]);
+    buf[40..].copy_from_slice(data);
+    buf
+}
+
+pub fn syn(port: u16, seq: u32) -> Vec<u8> {
+    segment(port, seq, 0, "S", &[])
+}
+
+/// TCP header of an IP packet without options
+pub fn tcp(packet: &[u8]) -> TCPHeader {
+    TCPHeader::from(&packet[20..]).unwrap()
+}
+
+/// Simulated interface on `LOCAL` with a non-blocking listener on port 9000 and a peer on `REMOTE`
+pub fn setup(seed: u64) -> (Simulation, Interface, Endpoint, TCPListener) {
+    let mut simulation = Simulation::new(seed);
+    let mut interface = simulation.add_interface(LOCAL).unwrap();
+    let peer = simulation.add_endpoint(REMOTE).unwrap();
+    let mut listener = interface.bind((LOCAL, 9000)).unwrap();
+    listener.set_nonblocking(true).unwrap();
+    (simulation, interface, peer, listener)
+}
+
+/// Connection opened by `peer` from `port` and accepted on `listener`.
+/// The peer starts at sequence number 100, so its next one is 101.
+pub fn handshake(simulation: &mut Simulation, listener: &mut TCPListener, peer: &Endpoint, port: u16) -> TCPStream {
+    peer.send(&syn(port, 100));
+    let mut received = None;
+    assert!(simulation.run_until(Duration::from_secs(1), || { received = peer.recv(); received.is_some() }), "No SYN-ACK");
+    let (_, synAck) = received.unwrap();
+    let tcph = tcp(&synAck);
+    assert!(tcph.syn && tcph.ack);
+    peer.send(&segment(port, 101, tcph.sequenceNumber + 1, "A", &[]));
+    simulation.advance(Duration::from_millis(10));
+    listener.accept().expect("Connection not accepted")
+}
+
+/// Split an IP packet without options into fragments of `size` payload bytes (a multiple of 8)
+pub fn fragments(packet: &[u8], id: u16, size: usize) -> Vec<Vec<u8>> {
+    let payload = &packet[20..];
+    payload.chunks(size).enumerate().map(|(i, chunk)| {
+        let mut fragment = packet[..20].to_vec();
+        fragment.extend_from_slice(chunk);
+        let len = fragment.len() as u16;
+        let mut view = Ipv4Packet::newUnchecked(&mut fragment[..]);
+        view.setTotalLength(len);
+        view.setIdentification(id);
+        view.setDontFragment(false);
+        view.setMoreFragments((i + 1) * size < payload.len());
+        view.setFragmentOffset((i * size / 8) as u16);
+        view.fillChecksum();
+        fragment
+    }).collect()
+}
+
+/// Classic pcap with raw IP link type and no direction information
+pub fn pcap(packets: &[(u32, Vec<u8>)]) -> Vec<u8> {
+    let mut file = Vec::new();
+    // Magic, version 2.4, timezone, accuracy, snaplen, link type
+    for value in [0xA1B2_C3D4u32, 4 << 16 | 2, 0, 0, 65535, 101] {
+        file.extend_from_slice(&value.to_le_bytes());
+    }
+    for (micros, packet) in packets {
+        for value in [micros / 1_000_000, micros % 1_000_000, packet.len() as u32, packet.len() as u32] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+        file.extend_from_slice(packet);
+    }
+    file
+}
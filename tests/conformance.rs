@@ -0,0 +1,36 @@
+//! Scripts in `tests/scripts` describing the stack's behaviour on the wire
+use TCP::Script;
+
+fn run(name: &str) {
+    let path = format!("{}/tests/scripts/{}.pkt", env!("CARGO_MANIFEST_DIR"), name);
+    let text = std::fs::read_to_string(&path).unwrap();
+    if let Err(error) = Script::parse(&text).and_then(|script| script.run()) {
+        panic!("{}.pkt {}", name, error);
+    }
+}
+
+#[test] fn handshake() { run("handshake") }
+#[test] fn data() { run("data") }
+#[test] fn passive_close() { run("passive-close") }
+#[test] fn reset() { run("reset") }
+#[test] fn reset_out_of_window() { run("reset-out-of-window") }
+#[test] fn unacceptable() { run("unacceptable") }
+#[test] fn abort() { run("abort") }
+#[test] fn keepalive() { run("keepalive") }
+#[test] fn keepalive_answered() { run("keepalive-answered") }
+#[test] fn keepalive_reset_out_of_window() { run("keepalive-reset-out-of-window") }
+#[test] fn no_listener() { run("no-listener") }
+#[test] fn listener_backlog() { run("listener-backlog") }
+
+#[test]
+fn runner_reports_mismatch() {
+    let script = Script::parse("0 bind 8080\n0.1 < S 100:100(0)\n+0 > S. 0:0(0) ack 999\n").unwrap();
+    let error = script.run().unwrap_err();
+    assert_eq!(error.line, 3);
+    assert!(error.message.contains("ack 101"), "{}", error);
+
+    let script = Script::parse("0 bind 8080\n0.1 < S 100:100(0)\n").unwrap();
+    assert!(script.run().unwrap_err().message.starts_with("unexpected S."));
+
+    assert_eq!(Script::parse("0 bind 8080\n0.1 < S 100:105(0)\n").unwrap_err().line, 2);
+}
@@ -0,0 +1,62 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use std::io::{self, Read};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use TCP::{Device, Interface};
+use common::*;
+
+/// Delivers the packets sent over `packets` and fails with `Other` on `None`
+#[derive(Debug, Clone)]
+struct FailingDevice {
+    packets: Arc<Mutex<Receiver<Option<Vec<u8>>>>>,
+}
+
+impl Device for FailingDevice {
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.packets.lock().unwrap().recv() {
+            Ok(Some(packet)) => {
+                buf[..packet.len()].copy_from_slice(&packet);
+                Ok(packet.len())
+            },
+            _ => Err(io::Error::other("link down"))
+        }
+    }
+
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Device>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+/// Device errors are passed on as the device reported them
+fn isDeviceError(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::Other && error.to_string() == "link down"
+}
+
+#[test]
+fn device_failure_wakes_blocked_reader_and_listener() {
+    let (packets, received) = channel();
+    let device = FailingDevice { packets: Arc::new(Mutex::new(received)) };
+    let mut interface = Interface::with_device(device).unwrap();
+    let mut listener = interface.bind((LOCAL, 9000)).unwrap();
+    packets.send(Some(syn(40000, 100))).unwrap();
+    let mut stream = listener.accept().unwrap();
+
+    let reader = thread::spawn(move || stream.read(&mut [0u8; 16]));
+    thread::sleep(Duration::from_millis(50));
+    packets.send(None).unwrap();
+
+    let error = reader.join().unwrap().unwrap_err();
+    assert!(isDeviceError(&error), "{:?}", error);
+    let error = listener.accept().unwrap_err();
+    assert!(isDeviceError(&error), "{:?}", error);
+}
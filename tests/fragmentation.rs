@@ -0,0 +1,165 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use common::{handshake, segment, setup, LOCAL, REMOTE};
+use TCP::{Device, ImpairedDevice, Impairment, Interface, Simulation};
+use TCP::Parser::{IPHeader, IPOption, Ipv4Packet};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// `packet` with DF cleared and `options` added
+fn fragmentable(packet: &[u8], options: &[IPOption]) -> Vec<u8> {
+    let mut iph = IPHeader::from(packet).unwrap();
+    iph.setOptions(options).unwrap();
+    iph.dontFragment = false;
+    let mut out = vec![0u8; iph.totalLength as usize];
+    iph.serialize(&mut out);
+    out[iph.size()..].copy_from_slice(&packet[20..]);
+    out
+}
+
+#[test]
+fn large_datagrams_are_fragmented_when_df_is_clear() {
+    let mut simulation = Simulation::new(1);
+    simulation.set_mtu(576);
+    let sender = simulation.add_interface(REMOTE).unwrap();
+    let receiver = simulation.add_endpoint(LOCAL).unwrap();
+
+    let packet = fragmentable(&segment(40000, 1, 0, "PA", &[7; 1360]), &[]);
+    sender.send_datagram(&packet).unwrap();
+    simulation.advance(Duration::from_millis(10));
+
+    let mut payload = Vec::new();
+    let mut layout = Vec::new();
+    while let Some((_, fragment)) = receiver.recv() {
+        let view = Ipv4Packet::new(&fragment[..]).unwrap();
+        assert!(fragment.len() <= 576 && view.verifyChecksum());
+        assert_eq!(view.identification(), Ipv4Packet::new(&packet[..]).unwrap().identification());
+        layout.push((view.fragmentOffset(), view.moreFragments(), view.payload().len()));
+        payload.extend_from_slice(view.payload());
+    }
+    assert_eq!(layout, [(0, true, 552), (69, true, 552), (138, false, 276)]);
+    assert_eq!(payload, &packet[20..]);
+
+    let stats = sender.stats();
+    assert_eq!((stats.fragOKs, stats.fragCreates, stats.fragFails), (1, 3, 0));
+}
+
+#[test]
+fn df_packets_larger_than_mtu_are_dropped() {
+    let mut simulation = Simulation::new(1);
+    simulation.set_mtu(576);
+    let sender = simulation.add_interface(REMOTE).unwrap();
+    let receiver = simulation.add_endpoint(LOCAL).unwrap();
+
+    let packet = segment(40000, 1, 0, "PA", &[7; 1360]);
+    assert!(Ipv4Packet::new(&packet[..]).unwrap().dontFragment());
+    assert!(sender.send_datagram(&packet).is_err());
+    sender.send_datagram(&segment(40000, 1, 0, "PA", &[7; 536])).unwrap();
+    simulation.advance(Duration::from_millis(10));
+
+    assert_eq!(receiver.recv().unwrap().1.len(), 576);
+    assert!(receiver.recv().is_none());
+    assert_eq!(sender.stats().fragFails, 1);
+}
+
+#[test]
+fn only_copied_options_repeat_in_later_fragments() {
+    let mut simulation = Simulation::new(1);
+    simulation.set_mtu(200);
+    let sender = simulation.add_interface(REMOTE).unwrap();
+    let receiver = simulation.add_endpoint(LOCAL).unwrap();
+
+    let route = IPOption::RecordRoute { pointer: 4, route: vec![LOCAL; 2] };
+    let options = [IPOption::RouterAlert(0), route];
+    sender.send_datagram(&fragmentable(&segment(40000, 1, 0, "PA", &[7; 300]), &options)).unwrap();
+    simulation.advance(Duration::from_millis(10));
+
+    let (_, first) = receiver.recv().unwrap();
+    let first = IPHeader::from(&first).unwrap();
+    assert_eq!(first.getOptions().unwrap(), [options[0].clone(), options[1].clone(), IPOption::EndOfList]);
+    while let Some((_, fragment)) = receiver.recv() {
+        let header = IPHeader::from(&fragment).unwrap();
+        assert_eq!((header.size(), header.getOptions().unwrap()), (24, vec![IPOption::RouterAlert(0)]));
+    }
+}
+
+#[test]
+fn fragments_reassemble_at_receiving_stack() {
+    let mut simulation = Simulation::new(1);
+    simulation.set_mtu(300);
+    let sender = simulation.add_interface(REMOTE).unwrap();
+    let mut receiver = simulation.add_interface(LOCAL).unwrap();
+    let _listener = receiver.bind((LOCAL, 9000)).unwrap();
+
+    sender.send_datagram(&fragmentable(&segment(40000, 100, 0, "S", &[1; 1000]), &[])).unwrap();
+    simulation.advance(Duration::from_millis(10));
+
+    assert_eq!(sender.stats().fragCreates, 4);
+    let stats = receiver.stats();
+    assert_eq!((stats.reasmReqds, stats.reasmOKs, stats.passiveOpens), (4, 1, 1));
+}
+
+#[test]
+fn connection_dont_fragment_flag() {
+    let (mut simulation, _interface, peer, mut listener) = setup(1);
+    let stream = handshake(&mut simulation, &mut listener, &peer, 40000);
+    assert!(stream.dont_fragment());
+
+    let mut ack = |seq: u32| {
+        peer.send(&segment(40000, seq, 1, "PA", b"h"));
+        simulation.advance(Duration::from_millis(10));
+        let (_, ack) = peer.recv().unwrap();
+        let view = Ipv4Packet::new(&ack[..]).unwrap();
+        assert!(view.verifyChecksum());
+        view.dontFragment()
+    };
+    assert!(ack(101));
+    stream.set_dont_fragment(false);
+    assert!(!ack(102));
+}
+
+/// Device with a 576 byte MTU keeping every packet sent. Receives nothing.
+#[derive(Debug, Clone, Default)]
+struct SmallMtuDevice {
+    sent: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl Device for SmallMtuDevice {
+    fn recv(&self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::UnexpectedEof.into())
+    }
+
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sent.lock().unwrap().push(buf.to_vec());
+        Ok(buf.len())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Device>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn mtu(&self) -> usize {
+        576
+    }
+}
+
+#[test]
+fn impaired_device_keeps_mtu_of_wrapped_device() {
+    let device = SmallMtuDevice::default();
+    let impaired = ImpairedDevice::new(device.clone(), Impairment::default(), Impairment::default(), 1).unwrap();
+    let interface = Interface::with_device(impaired).unwrap();
+    interface.send_datagram(&fragmentable(&segment(40000, 1, 0, "PA", &[7; 1360]), &[])).unwrap();
+
+    // Sent by the impairment thread
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while device.sent.lock().unwrap().len() < 3 {
+        assert!(Instant::now() < deadline, "Fragments not sent");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(device.sent.lock().unwrap().iter().all(|fragment| fragment.len() <= 576));
+    let stats = interface.stats();
+    assert_eq!((stats.fragOKs, stats.fragCreates, stats.fragFails), (1, 3, 0));
+}
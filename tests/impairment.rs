@@ -0,0 +1,96 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use common::{pcap, syn, tcp, LOCAL, REMOTE};
+use TCP::{GilbertElliott, ImpairedDevice, Impairment, Interface, IPAddress, PcapReplay, Simulation};
+use std::time::{Duration, Instant};
+
+/// Sends a SYN from each of `count` ports and returns arrival time and port of every answer
+fn synRun(impairment: Impairment, seed: u64, count: u16) -> Vec<(Duration, u16)> {
+    let mut simulation = Simulation::new(seed);
+    simulation.set_impairment(impairment);
+    let mut interface = simulation.add_interface(LOCAL).unwrap();
+    let peer = simulation.add_endpoint(REMOTE).unwrap();
+    let _listener = interface.bind((LOCAL, 9000)).unwrap();
+
+    for port in 0..count {
+        peer.send(&syn(40000 + port, 100));
+    }
+    simulation.advance(Duration::from_secs(5));
+    std::iter::from_fn(|| peer.recv())
+        .map(|(time, packet)| (time, tcp(&packet).destinationPort))
+        .collect()
+}
+
+#[test]
+fn total_loss_drops_everything() {
+    assert!(synRun(Impairment { loss: 1.0, ..Impairment::default() }, 1, 10).is_empty());
+}
+
+#[test]
+fn random_loss_is_reproducible() {
+    let impairment = Impairment { loss: 0.3, ..Impairment::default() };
+    let run = synRun(impairment, 5, 100);
+    // A SYN-ACK needs both directions to survive
+    assert!(run.len() > 30 && run.len() < 70, "{} answered", run.len());
+    assert_eq!(run, synRun(impairment, 5, 100));
+}
+
+#[test]
+fn burst_loss_loses_consecutive_packets() {
+    let model = GilbertElliott { goodToBad: 0.05, badToGood: 0.2, lossGood: 0.0, lossBad: 1.0 };
+    let run = synRun(Impairment { burstLoss: Some(model), ..Impairment::default() }, 3, 400);
+    let answered: Vec<u16> = run.iter().map(|&(_, port)| port - 40000).collect();
+
+    // Count gaps between answered SYNs. Bursts make long gaps while the number of gaps stays small.
+    let gaps: Vec<u16> = answered.windows(2).map(|pair| pair[1] - pair[0] - 1).filter(|&gap| gap > 0).collect();
+    let lost: u16 = gaps.iter().sum();
+    assert!(!gaps.is_empty());
+    assert!(lost as f64 / gaps.len() as f64 > 2.0, "{} lost in {} gaps", lost, gaps.len());
+}
+
+#[test]
+fn rate_limit_spaces_packets() {
+    // 40 byte packets on a 40 kB/s link take 1ms each
+    let impairment = Impairment { rate: Some(40_000), ..Impairment::default() };
+    let run = synRun(impairment, 1, 10);
+    assert_eq!(run.len(), 10);
+    for pair in run.windows(2) {
+        assert!(pair[1].0 - pair[0].0 >= Duration::from_micros(999), "{:?}", pair);
+    }
+    assert!(run[9].0 >= Duration::from_millis(10));
+}
+
+#[test]
+fn duplication_and_reordering() {
+    let run = synRun(Impairment { duplicate: 1.0, ..Impairment::default() }, 1, 1);
+    // SYN arrives twice and each SYN-ACK is sent twice
+    assert!(run.len() >= 2);
+
+    let impairment = Impairment { reorder: 0.5, reorderDelay: Duration::from_millis(10), ..Impairment::default() };
+    let run = synRun(impairment, 2, 20);
+    assert_eq!(run.len(), 20);
+    assert!(run.windows(2).any(|pair| pair[1].1 < pair[0].1));
+}
+
+#[test]
+fn impaired_device_delays_real_packets() {
+    let replay = PcapReplay::from_reader(&pcap(&[(0, syn(40000, 100))])[..], LOCAL).unwrap();
+    replay.set_speed(None).unwrap();
+    let outbound = Impairment { latency: Duration::from_millis(200), duplicate: 1.0, ..Impairment::default() };
+    let device = ImpairedDevice::new(replay.clone(), Impairment::default(), outbound, 1).unwrap();
+
+    let mut interface = Interface::with_device(device).unwrap();
+    let _listener = interface.bind((IPAddress::UNSPECIFIED, 9000)).unwrap();
+    let start = Instant::now();
+    replay.start();
+    while replay.responses().len() < 2 && start.elapsed() < Duration::from_secs(5) {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let responses = replay.responses();
+    assert_eq!(responses.len(), 2);
+    assert!(responses[0].time >= Duration::from_millis(200));
+    assert_eq!(responses[0].data, responses[1].data);
+}
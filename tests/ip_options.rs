@@ -0,0 +1,140 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use common::{segment, setup, syn, tcp, LOCAL, REMOTE};
+use TCP::IPAddress;
+use TCP::Parser::{IPHeader, IPOption, IPOptionError, IPProtocol, Ipv4Packet};
+use std::time::Duration;
+
+fn allOptions() -> Vec<IPOption> {
+    vec![
+        IPOption::RouterAlert(0),
+        IPOption::Nop,
+        IPOption::Security { classification: 0xABCD, compartments: 1, handling: 2, tcc: [3, 4, 5] },
+        IPOption::RecordRoute { pointer: 8, route: vec![IPAddress::new(192, 0, 2, 7), IPAddress::UNSPECIFIED] },
+        IPOption::Timestamp { pointer: 5, overflow: 1, flag: 0, entries: vec![(None, 0)] },
+        IPOption::EndOfList,
+    ]
+}
+
+#[test]
+fn options_round_trip() {
+    let options = allOptions();
+    let mut buffer = [0xFFu8; 40];
+    let len = IPOption::serialize(&options, &mut buffer).unwrap();
+    // 4 + 1 + 11 + 11 + 8 + 1 bytes, padded to 36
+    assert_eq!(len, 36);
+    assert_eq!(&buffer[..4], &[148, 4, 0, 0]);
+    assert_eq!(IPOption::parse(&buffer[..len]).unwrap(), options);
+
+    let timestamps = [IPOption::Timestamp { pointer: 13, overflow: 0, flag: 1,
+                                            entries: vec![(Some(REMOTE), 1000), (Some(IPAddress::UNSPECIFIED), 0)] }];
+    let len = IPOption::serialize(&timestamps, &mut buffer).unwrap();
+    assert_eq!(&buffer[..len], &[68, 20, 13, 1, 10, 0, 0, 2, 0, 0, 3, 232, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(IPOption::parse(&buffer[..len]).unwrap(), timestamps);
+
+    let unknown = [IPOption::Unknown { kind: 25, data: vec![1, 2] }];
+    assert_eq!(IPOption::serialize(&unknown, &mut buffer), Ok(4));
+    assert_eq!(IPOption::parse(&buffer[..4]).unwrap(), unknown);
+}
+
+#[test]
+fn unrepresentable_options_are_not_serialized() {
+    let mut buffer = [0xFFu8; 40];
+    assert_eq!(IPOption::serialize(&allOptions(), &mut buffer[..35]), Err(IPOptionError::BufferTooSmall { needed: 36, len: 35 }));
+    assert_eq!(IPOption::serialize(&[IPOption::Nop], &mut []), Err(IPOptionError::BufferTooSmall { needed: 4, len: 0 }));
+
+    let overflow = [IPOption::Timestamp { pointer: 5, overflow: 16, flag: 0, entries: vec![(None, 0)] }];
+    assert_eq!(IPOption::serialize(&overflow, &mut buffer), Err(IPOptionError::BadLength { kind: 68, len: 8 }));
+    let addressed = [IPOption::Timestamp { pointer: 5, overflow: 0, flag: 0, entries: vec![(Some(REMOTE), 0)] }];
+    assert_eq!(IPOption::serialize(&addressed, &mut buffer), Err(IPOptionError::BadLength { kind: 68, len: 8 }));
+    assert_eq!(buffer, [0xFFu8; 40]);
+}
+
+#[test]
+fn set_options_keeps_payload_length() {
+    let options = [IPOption::RouterAlert(0)];
+    let mut iph = IPHeader::new(LOCAL, REMOTE, IPProtocol::Tcp, 64, 100);
+    iph.setOptions(&options).unwrap();
+    assert_eq!((iph.size(), iph.totalLength), (24, 124));
+    iph.setOptions(&[]).unwrap();
+    assert_eq!((iph.size(), iph.totalLength), (20, 120));
+
+    // Total length shorter than the header counts as no payload
+    iph.totalLength = 0;
+    iph.setOptions(&options).unwrap();
+    assert_eq!(iph.totalLength, 24);
+
+    iph.totalLength = u16::MAX;
+    assert_eq!(iph.setOptions(&allOptions()), Err(IPOptionError::PacketTooLong(65535 - 24 + 56)));
+    assert_eq!((iph.size(), iph.totalLength), (24, u16::MAX));
+}
+
+#[test]
+fn header_serializes_options_with_checksum() {
+    let mut iph = IPHeader::new(REMOTE, LOCAL, IPProtocol::Tcp, 64, 20);
+    iph.setOptions(&allOptions()).unwrap();
+    assert_eq!((iph.headerLength, iph.totalLength, iph.size()), (14, 76, 56));
+
+    let mut buffer = [0u8; 76];
+    iph.serialize(&mut buffer);
+    assert!(IPHeader::verifyChecksum(&buffer));
+    let parsed = IPHeader::from(&buffer).unwrap();
+    assert_eq!(parsed.getOptions().unwrap(), allOptions());
+    assert_eq!(Ipv4Packet::new(&buffer[..]).unwrap().options(), &buffer[20..56]);
+
+    iph.setOptions(&[]).unwrap();
+    assert_eq!((iph.headerLength, iph.totalLength), (5, 40));
+    assert_eq!(iph.setOptions(&[IPOption::Unknown { kind: 25, data: vec![0; 39] }]), Err(IPOptionError::TooLong(41)));
+}
+
+#[test]
+fn malformed_options_are_rejected() {
+    for (options, error) in [
+        (&[148, 3, 0, 0][..], IPOptionError::BadLength { kind: 148, len: 3 }),
+        (&[1, 7, 8, 4, 0][..], IPOptionError::BadLength { kind: 7, len: 8 }),
+        (&[7, 7, 3, 0, 0, 0, 0, 0][..], IPOptionError::BadLength { kind: 7, len: 7 }),
+        (&[68, 8, 5, 1, 0, 0, 0, 0][..], IPOptionError::BadLength { kind: 68, len: 8 }),
+        (&[130, 10, 0, 0, 0, 0, 0, 0, 0, 0][..], IPOptionError::BadLength { kind: 130, len: 10 }),
+        (&[25][..], IPOptionError::BadLength { kind: 25, len: 0 }),
+    ] {
+        assert_eq!(IPOption::parse(options), Err(error));
+        assert_eq!(IPOption::check(options), Err(error));
+    }
+    // Anything after End of Option List is padding
+    assert_eq!(IPOption::parse(&[1, 0, 25, 0]).unwrap(), [IPOption::Nop, IPOption::EndOfList]);
+}
+
+/// `packet` with `options` inserted after the fixed IP header
+fn withOptions(packet: &[u8], options: &[IPOption]) -> Vec<u8> {
+    let mut iph = IPHeader::from(packet).unwrap();
+    iph.setOptions(options).unwrap();
+    let mut out = vec![0u8; iph.totalLength as usize];
+    iph.serialize(&mut out);
+    out[iph.size()..].copy_from_slice(&packet[20..]);
+    out
+}
+
+#[test]
+fn stack_accepts_segments_with_options() {
+    let (mut simulation, interface, peer, mut listener) = setup(3);
+
+    let route = [IPOption::RecordRoute { pointer: 4, route: vec![IPAddress::UNSPECIFIED; 2] }];
+    peer.send(&withOptions(&syn(40000, 100), &route));
+    simulation.advance(Duration::from_millis(10));
+    let (_, synAck) = peer.recv().expect("SYN-ACK");
+    assert_eq!(tcp(&synAck).acknowledgementNumber, 101);
+
+    peer.send(&withOptions(&segment(40000, 101, 1, "A", &[]), &[IPOption::RouterAlert(0)]));
+    simulation.advance(Duration::from_millis(10));
+    assert!(listener.accept().is_ok());
+
+    // Option running past the header is malformed, whatever the checksum says
+    let mut bad = withOptions(&syn(40001, 100), &[IPOption::RouterAlert(0)]);
+    bad[21] = 8;
+    peer.send(&bad);
+    simulation.advance(Duration::from_millis(10));
+    assert!(peer.recv().is_none());
+    assert_eq!(interface.stats().drops.malformed, 1);
+}
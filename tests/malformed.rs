@@ -0,0 +1,177 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use common::{handshake, segment, setup, syn, LOCAL, REMOTE};
+use TCP::Parser::{EthernetHeader, IPHeader, Ipv4Packet, TCPHeader, TCPHeaderError, TcpOptionElement};
+use std::io::Read;
+use std::time::Duration;
+
+/// Recompute IP header checksum after editing the header. Packets whose header
+/// does not fit are rejected before their checksum is looked at, so they keep theirs.
+fn fillChecksum(packet: &mut [u8]) {
+    if Ipv4Packet::newUnchecked(&*packet).size() <= packet.len() {
+        Ipv4Packet::newUnchecked(packet).fillChecksum();
+    }
+}
+
+#[test]
+fn short_buffers_are_errors() {
+    assert!(EthernetHeader::from(&[0u8; 13]).is_err());
+    assert!(EthernetHeader::from(&[0u8; 14]).is_ok());
+    assert!(IPHeader::from(&syn(40000, 1)[..19]).is_err());
+    assert_eq!(TCPHeader::from(&[0u8; 19]).err(), Some(TCPHeaderError::TooShort(19)));
+    assert!(TCPHeader::from(&syn(40000, 1)[20..]).is_ok());
+}
+
+#[test]
+fn tcp_data_offset_and_options_are_checked() {
+    let mut header = syn(40000, 1)[20..].to_vec();
+    header[12] = 0x40;
+    assert_eq!(TCPHeader::from(&header).err(), Some(TCPHeaderError::DataOffsetTooSmall(4)));
+    header[12] = 0x60;
+    assert_eq!(TCPHeader::from(&header).err(), Some(TCPHeaderError::DataOffsetTooLarge { dataOffset: 6, len: 20 }));
+
+    // MSS option, then one running past the end of the header
+    header.extend_from_slice(&[2, 4, 0x05, 0xB4]);
+    let parsed = TCPHeader::from(&header).unwrap();
+    assert_eq!((parsed.size(), &parsed.options[..4]), (24, &[2, 4, 0x05, 0xB4][..]));
+    header[12] = 0x70;
+    header.extend_from_slice(&[1, 3, 8, 10]);
+    assert_eq!(TCPHeader::from(&header).err(), Some(TCPHeaderError::BadOptionLength { kind: 3, len: 8 }));
+    header[26] = 0;
+    assert_eq!(TCPHeader::from(&header).err(), Some(TCPHeaderError::BadOptionLength { kind: 3, len: 0 }));
+    header[25] = 0;
+    assert!(TCPHeader::from(&header).is_ok());
+}
+
+#[test]
+fn tcp_options_round_trip() {
+    let options = vec![
+        TcpOptionElement::MaximumSegmentSize(1460),
+        TcpOptionElement::Nop,
+        TcpOptionElement::WindowScale(7),
+        TcpOptionElement::SelectiveAcknowledgementPermitted,
+        TcpOptionElement::Timestamp(1, 2),
+        TcpOptionElement::SelectiveAcknowledgement((10, 20), [Some((30, 40)), None, None]),
+    ];
+    let mut tcph = TCPHeader::new(40000, 9000, 1, 1024);
+    tcph.setOptions(&options).unwrap();
+    assert_eq!(tcph.size(), 20 + 40);
+
+    // Serialized with the options and a checksum covering them
+    let mut segment = vec![0u8; tcph.size() + 3];
+    tcph.calcChecksum(REMOTE, LOCAL, b"abc");
+    tcph.serialize(&mut segment);
+    segment[tcph.size()..].copy_from_slice(b"abc");
+    assert!(TCPHeader::verifyChecksum(&segment, REMOTE, LOCAL));
+    assert_eq!(TCPHeader::from(&segment).unwrap().optionElements().unwrap(), options);
+
+    // Padded to whole words, too long for the header, unknown kind
+    tcph.setOptions(&[TcpOptionElement::WindowScale(2)]).unwrap();
+    assert_eq!((tcph.size(), &tcph.options[..4]), (24, &[3, 3, 2, 0][..]));
+    assert_eq!(tcph.setOptions(&vec![TcpOptionElement::Timestamp(1, 2); 5]).err(), Some(TCPHeaderError::OptionsTooLong(50)));
+    tcph.options[..4].copy_from_slice(&[30, 4, 0, 0]);
+    assert_eq!(tcph.optionElements().err(), Some(TCPHeaderError::UnknownOption { kind: 30, len: 4 }));
+}
+
+#[test]
+fn bad_ip_lengths_are_dropped() {
+    let (mut simulation, interface, peer, _listener) = setup(3);
+
+    // Header length longer than packet, shorter than minimum, total length past the end
+    let mut cases = Vec::new();
+    for (offset, value) in [(0, 0x4F), (0, 0x44), (3, 200)] {
+        let mut packet = syn(40000, 1);
+        packet[offset] = value;
+        fillChecksum(&mut packet);
+        cases.push(packet);
+    }
+    for packet in &cases {
+        assert!(IPHeader::from(packet).is_err());
+        peer.send(packet);
+    }
+    simulation.advance(Duration::from_secs(1));
+
+    assert!(peer.recv().is_none());
+    assert_eq!(interface.stats().drops.malformed, 3);
+}
+
+#[test]
+fn bad_tcp_lengths_are_dropped() {
+    let (mut simulation, interface, peer, _listener) = setup(3);
+
+    // TCP header cut short by IP total length
+    let mut truncated = syn(40000, 1);
+    truncated[3] = 30;
+    fillChecksum(&mut truncated);
+    // Data offset past the end of the packet
+    let mut offset = syn(40001, 1);
+    offset[32] = 0xF0;
+    peer.send(&truncated);
+    peer.send(&offset);
+    simulation.advance(Duration::from_secs(1));
+
+    assert!(peer.recv().is_none());
+    assert_eq!(interface.stats().drops.malformed, 2);
+}
+
+#[test]
+fn link_padding_is_ignored() {
+    let (mut simulation, _interface, peer, mut listener) = setup(3);
+    let mut stream = handshake(&mut simulation, &mut listener, &peer, 40000);
+    stream.set_nonblocking(true).unwrap();
+
+    // Bytes past the IP total length are not payload
+    let mut padded = segment(40000, 101, 1, "PA", b"hello");
+    padded.extend_from_slice(&[0; 6]);
+    peer.send(&padded);
+    simulation.advance(Duration::from_millis(10));
+    let mut buf = [0u8; 20];
+    assert_eq!(stream.read(&mut buf).unwrap(), 5);
+    assert_eq!(&buf[..5], b"hello");
+}
+
+/// Cheap stand in for the fuzz targets: random corruption of valid packets must not panic
+#[test]
+fn corrupted_packets_do_not_panic() {
+    let (mut simulation, _interface, peer, mut listener) = setup(3);
+    let valid = [
+        syn(40000, 100),
+        segment(40000, 101, 1, "A", &[]),
+        segment(40000, 101, 1, "PA", b"hello"),
+        segment(40000, 106, 1, "FA", &[]),
+        segment(40000, 106, 1, "RA", &[]),
+    ];
+
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let mut streams = Vec::new();
+    for _ in 0..5000 {
+        let mut packet = valid[random() as usize % valid.len()].clone();
+        for _ in 0..random() % 4 {
+            let at = random() as usize % packet.len();
+            packet[at] = random() as u8;
+        }
+        packet.truncate(20 + random() as usize % packet.len());
+        if random() % 2 == 0 {
+            fillChecksum(&mut packet);
+        }
+        peer.send(&packet);
+        simulation.advance(Duration::from_millis(random() % 50));
+
+        while let Ok(stream) = listener.accept() {
+            stream.set_nonblocking(true).unwrap();
+            streams.push(stream);
+        }
+        for stream in &mut streams {
+            let _ = stream.read(&mut [0u8; 1500]);
+        }
+        while peer.recv().is_some() {}
+    }
+}
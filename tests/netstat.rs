@@ -0,0 +1,44 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use common::{handshake, segment, setup, syn};
+use TCP::{Keepalive, TCPState};
+use std::io::Write;
+use std::time::Duration;
+
+#[test]
+fn table_renders_like_ss() {
+    let (mut simulation, mut interface, peer, mut listener) = setup(1);
+    let _idle = interface.bind((TCP::IPAddress::UNSPECIFIED, 8080)).unwrap();
+
+    let mut stream = handshake(&mut simulation, &mut listener, &peer, 40000);
+    peer.send(&segment(40000, 101, 1, "PA", b"hello"));
+    // Second connection still waits in the backlog
+    peer.send(&syn(40001, 500));
+    simulation.advance(Duration::from_millis(10));
+
+    stream.set_keepalive(Some(Keepalive { idle: Duration::from_secs(60), interval: Duration::from_secs(5), probes: 3 }));
+    assert_eq!(stream.write(b"abc").unwrap(), 3);
+    simulation.advance(Duration::from_secs(2));
+
+    let table = interface.connections();
+    // Columns are padded, so lines end in spaces
+    let rendered = table.to_string();
+    let rendered: Vec<&str> = rendered.lines().map(str::trim_end).collect();
+    assert_eq!(rendered, [
+        "State       Recv-Q  Send-Q Local Address:Port        Peer Address:Port",
+        "LISTEN           0       0 0.0.0.0:8080              *:*",
+        "LISTEN           1       0 10.0.0.1:9000             *:*",
+        "ESTAB            5       3 10.0.0.1:9000             10.0.0.2:40000",
+        "SYN-RECV         0       0 10.0.0.1:9000             10.0.0.2:40001",
+    ]);
+
+    let established = &table.connections[0];
+    assert_eq!(established.state, TCPState::Estab);
+    assert_eq!((established.recv.irs, established.recv.nxt), (100, 106));
+    // Last packet arrived 9ms before the 2s wait started
+    assert_eq!(established.timers.idle, Duration::from_millis(2009));
+    assert_eq!(established.timers.keepalive, Some(Duration::from_millis(57991)));
+    assert_eq!(established.timers.userTimeout, None);
+}
@@ -0,0 +1,83 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use common::{segment, syn, tcp, LOCAL, REMOTE};
+use TCP::Parser::{IPHeader, Ipv4Packet, TcpSegment, TCPHeaderError};
+
+#[test]
+fn views_read_same_fields_as_parsers() {
+    let packet = segment(40000, 101, 7, "PA", b"hello");
+    let ip = Ipv4Packet::new(&packet[..]).unwrap();
+    let iph = IPHeader::from(&packet).unwrap();
+    assert!(ip.verifyChecksum());
+    assert_eq!((ip.version(), ip.size(), ip.totalLength(), ip.ttl(), ip.protocol()),
+               (4, iph.size(), iph.totalLength, iph.ttl, iph.protocol));
+    assert_eq!((ip.sourceIP(), ip.destinationIP(), ip.dontFragment()), (REMOTE, LOCAL, iph.dontFragment));
+
+    let view = TcpSegment::new(ip.payload()).unwrap();
+    let tcph = tcp(&packet);
+    assert_eq!((view.sourcePort(), view.destinationPort()), (tcph.sourcePort, tcph.destinationPort));
+    assert_eq!((view.sequenceNumber(), view.acknowledgementNumber()), (101, 7));
+    assert_eq!((view.window(), view.checksum(), view.size()), (tcph.window, tcph.checksum, 20));
+    assert_eq!((view.psh(), view.ack(), view.syn(), view.flags()), (true, true, false, tcph.flags()));
+    assert_eq!((view.options(), view.payload()), (&[][..], &b"hello"[..]));
+    assert!(view.verifyChecksum(REMOTE, LOCAL));
+}
+
+#[test]
+fn payload_excludes_link_padding() {
+    let mut packet = segment(40000, 101, 1, "PA", b"hi");
+    packet.extend_from_slice(&[0; 6]);
+    let ip = Ipv4Packet::new(&packet[..]).unwrap();
+    assert_eq!(ip.payload().len(), 22);
+    assert_eq!(TcpSegment::new(ip.payload()).unwrap().payload(), b"hi");
+}
+
+#[test]
+fn setters_write_in_place() {
+    let mut packet = syn(40000, 100);
+    let mut ip = Ipv4Packet::new(&mut packet[..]).unwrap();
+    ip.setTtl(3);
+    ip.setIdentification(0xBEEF);
+    ip.fillChecksum();
+    assert!(ip.verifyChecksum());
+
+    let mut view = TcpSegment::new(ip.payloadMut()).unwrap();
+    view.setSequenceNumber(5000);
+    view.setAcknowledgementNumber(42);
+    view.setControlBits(0b00010010);
+    view.setWindow(512);
+    assert!(!view.verifyChecksum(REMOTE, LOCAL));
+    view.fillChecksum(REMOTE, LOCAL);
+    assert!(view.verifyChecksum(REMOTE, LOCAL));
+
+    let iph = IPHeader::from(&packet).unwrap();
+    assert_eq!((iph.ttl, iph.identification), (3, 0xBEEF));
+    let tcph = tcp(&packet);
+    assert_eq!((tcph.sequenceNumber, tcph.acknowledgementNumber, tcph.window), (5000, 42, 512));
+    assert_eq!(tcph.flags(), "AS");
+}
+
+#[test]
+fn invalid_buffers_are_rejected() {
+    let packet = syn(40000, 1);
+    assert!(Ipv4Packet::new(&packet[..19]).is_err());
+    let mut bad = packet.clone();
+    bad[0] = 0x65;
+    assert!(Ipv4Packet::new(&bad[..]).is_err());
+    bad[0] = 0x44;
+    assert!(Ipv4Packet::new(&bad[..]).is_err());
+    bad[0] = 0x45;
+    bad[3] = 41;
+    assert!(Ipv4Packet::new(&bad[..]).is_err());
+
+    assert_eq!(TcpSegment::new(&[0u8; 19][..]).err(), Some(TCPHeaderError::TooShort(19)));
+    let mut header = packet[20..].to_vec();
+    header[12] = 0x60;
+    assert_eq!(TcpSegment::new(&header[..]).err(), Some(TCPHeaderError::DataOffsetTooLarge { dataOffset: 6, len: 20 }));
+    header.extend_from_slice(&[2, 9, 0, 0]);
+    assert_eq!(TcpSegment::new(&header[..]).err(), Some(TCPHeaderError::BadOptionLength { kind: 2, len: 9 }));
+    header[21] = 4;
+    assert_eq!(TcpSegment::new(&header[..]).unwrap().options(), &[2, 4, 0, 0]);
+}
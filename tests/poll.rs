@@ -0,0 +1,151 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use common::{handshake, segment, setup, syn, tcp};
+use TCP::{Endpoint, Events, Interest, Poller, Simulation, TCPListener, TCPStream, Token};
+use std::io::{ErrorKind, Read};
+use std::time::Duration;
+
+const LISTENER: Token = Token(0);
+const STREAM: Token = Token(1);
+
+/// Events ready now, without waiting
+fn ready(poller: &Poller) -> Vec<(Token, bool, bool, bool)> {
+    let mut events = Events::new();
+    poller.poll(&mut events, Some(Duration::from_millis(0))).unwrap();
+    events.iter().map(|event| (event.token(), event.is_readable(), event.is_writable(), event.is_closed())).collect()
+}
+
+/// Open a connection from `port`, checking the poller reports the listener readable
+fn accept(simulation: &mut Simulation, poller: &Poller, listener: &mut TCPListener, peer: &Endpoint, port: u16) -> TCPStream {
+    let stream = handshake(simulation, listener, peer, port);
+    assert_eq!(ready(poller), [(LISTENER, true, false, false)]);
+    stream.set_nonblocking(true).unwrap();
+    stream
+}
+
+/// Common setup with the listener registered on a poller
+fn pollSetup() -> (Simulation, TCP::Interface, Endpoint, TCPListener, Poller) {
+    let (simulation, interface, peer, listener) = setup(1);
+    let poller = interface.poller();
+    poller.register_listener(&listener, LISTENER, Interest::READABLE).unwrap();
+    (simulation, interface, peer, listener, poller)
+}
+
+#[test]
+fn readiness_is_reported_once_per_edge() {
+    let (mut simulation, _interface, peer, mut listener, poller) = pollSetup();
+    let mut stream = accept(&mut simulation, &poller, &mut listener, &peer, 40000);
+    assert!(ready(&poller).is_empty(), "Listener reported twice");
+
+    // Writable as soon as it is registered, then quiet until something changes
+    poller.register(&stream, STREAM, Interest::BOTH).unwrap();
+    assert_eq!(ready(&poller), [(STREAM, false, true, false)]);
+    assert!(ready(&poller).is_empty());
+
+    peer.send(&segment(40000, 101, 1, "PA", b"hello"));
+    simulation.advance(Duration::from_millis(10));
+    assert_eq!(ready(&poller), [(STREAM, true, false, false)]);
+    assert!(ready(&poller).is_empty(), "Readable reported twice");
+
+    // Still unread data does not produce a new event
+    simulation.advance(Duration::from_secs(1));
+    assert!(ready(&poller).is_empty());
+    let mut buf = [0u8; 10];
+    assert_eq!(stream.read(&mut buf).unwrap(), 5);
+    assert_eq!(stream.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+    peer.send(&segment(40000, 106, 1, "PA", b"again"));
+    simulation.advance(Duration::from_millis(10));
+    assert_eq!(ready(&poller), [(STREAM, true, false, false)]);
+}
+
+#[test]
+fn writable_while_handshake_completes() {
+    let (mut simulation, _interface, peer, mut listener, poller) = pollSetup();
+    peer.send(&syn(40000, 100));
+    simulation.advance(Duration::from_millis(10));
+    let (_, synAck) = peer.recv().unwrap();
+    assert_eq!(ready(&poller), [(LISTENER, true, false, false)]);
+
+    // Accepted while the handshake is still going on, writes are queued already
+    let stream = listener.accept().unwrap();
+    poller.register(&stream, STREAM, Interest::BOTH).unwrap();
+    assert_eq!(ready(&poller), [(STREAM, false, true, false)]);
+
+    // Completing it changes nothing
+    peer.send(&segment(40000, 101, tcp(&synAck).sequenceNumber + 1, "A", &[]));
+    simulation.advance(Duration::from_millis(10));
+    assert!(ready(&poller).is_empty(), "Writable reported twice");
+}
+
+#[test]
+fn interest_filters_events() {
+    let (mut simulation, _interface, peer, mut listener, poller) = pollSetup();
+    let stream = accept(&mut simulation, &poller, &mut listener, &peer, 40000);
+
+    poller.register(&stream, STREAM, Interest::WRITABLE).unwrap();
+    assert_eq!(ready(&poller), [(STREAM, false, true, false)]);
+    peer.send(&segment(40000, 101, 1, "PA", b"hello"));
+    simulation.advance(Duration::from_millis(10));
+    assert!(ready(&poller).is_empty());
+
+    // Registering again reports what is ready already
+    poller.register(&stream, STREAM, Interest::READABLE).unwrap();
+    assert_eq!(ready(&poller), [(STREAM, true, false, false)]);
+}
+
+#[test]
+fn reset_is_reported_as_closed() {
+    let (mut simulation, _interface, peer, mut listener, poller) = pollSetup();
+    let mut stream = accept(&mut simulation, &poller, &mut listener, &peer, 40000);
+    poller.register(&stream, STREAM, Interest::READABLE).unwrap();
+
+    peer.send(&segment(40000, 101, 1, "R", &[]));
+    simulation.advance(Duration::from_millis(10));
+    assert_eq!(ready(&poller), [(STREAM, true, false, true)]);
+    assert_eq!(stream.read(&mut [0u8; 10]).unwrap_err().kind(), ErrorKind::ConnectionReset);
+    assert!(ready(&poller).is_empty());
+}
+
+#[test]
+fn fin_is_reported_as_readable_then_closed() {
+    let (mut simulation, _interface, peer, mut listener, poller) = pollSetup();
+    let mut stream = accept(&mut simulation, &poller, &mut listener, &peer, 40000);
+    poller.register(&stream, STREAM, Interest::READABLE).unwrap();
+
+    peer.send(&segment(40000, 101, 1, "FA", &[]));
+    simulation.advance(Duration::from_millis(10));
+    assert_eq!(ready(&poller), [(STREAM, true, false, false)]);
+    assert_eq!(stream.read(&mut [0u8; 10]).unwrap(), 0);
+
+    // Our FIN went out right away, its ACK ends the connection
+    let fin = std::iter::from_fn(|| peer.recv()).map(|(_, packet)| tcp(&packet)).find(|tcph| tcph.fin).unwrap();
+    peer.send(&segment(40000, 102, fin.sequenceNumber + 1, "A", &[]));
+    simulation.advance(Duration::from_millis(10));
+    assert_eq!(ready(&poller), [(STREAM, true, false, true)]);
+    assert_eq!(stream.read(&mut [0u8; 10]).unwrap(), 0);
+}
+
+#[test]
+fn deregistered_sources_are_quiet() {
+    let (mut simulation, _interface, peer, mut listener, poller) = pollSetup();
+    let stream = accept(&mut simulation, &poller, &mut listener, &peer, 40000);
+    poller.register(&stream, STREAM, Interest::READABLE).unwrap();
+    poller.deregister(&stream).unwrap();
+    poller.deregister_listener(&listener).unwrap();
+
+    handshake(&mut simulation, &mut listener, &peer, 40001);
+    peer.send(&segment(40000, 101, 1, "PA", b"hello"));
+    simulation.advance(Duration::from_millis(10));
+    assert!(ready(&poller).is_empty());
+}
+
+#[test]
+fn poll_rejects_sources_of_other_interfaces() {
+    let (mut simulation, _interface, _peer, _listener, poller) = pollSetup();
+    let mut other = simulation.add_interface(TCP::IPAddress::new(10, 0, 0, 3)).unwrap();
+    let listener = other.bind((TCP::IPAddress::new(10, 0, 0, 3), 9000)).unwrap();
+    assert_eq!(poller.register_listener(&listener, Token(5), Interest::READABLE).unwrap_err().kind(), ErrorKind::InvalidInput);
+}
@@ -0,0 +1,133 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use common::{fragments, handshake, segment, setup, tcp};
+use TCP::{Endpoint, Interface, Simulation, TCPStream};
+use TCP::Parser::Ipv4Packet;
+use std::io::Read;
+use std::time::Duration;
+
+/// Established connection from `REMOTE:40000`, next sequence number from the peer is 101
+fn connect() -> (Simulation, Interface, Endpoint, TCPStream) {
+    let (mut simulation, interface, peer, mut listener) = setup(9);
+    let stream = handshake(&mut simulation, &mut listener, &peer, 40000);
+    stream.set_nonblocking(true).unwrap();
+    (simulation, interface, peer, stream)
+}
+
+fn data() -> Vec<u8> {
+    (0..100u8).collect()
+}
+
+fn readAll(stream: &mut TCPStream) -> Vec<u8> {
+    let mut buf = [0u8; 1500];
+    let len = stream.read(&mut buf).unwrap_or(0);
+    buf[..len].to_vec()
+}
+
+#[test]
+fn fragments_are_reassembled_in_any_order() {
+    let (mut simulation, interface, peer, mut stream) = connect();
+    let packet = segment(40000, 101, 1, "PA", &data());
+    let mut parts = fragments(&packet, 7, 24);
+    assert_eq!(parts.len(), 5);
+    let last = Ipv4Packet::new(&parts[4][..]).unwrap();
+    assert!(!last.moreFragments() && last.fragmentOffset() == 12);
+
+    parts.swap(0, 3);
+    parts.swap(1, 4);
+    for part in &parts {
+        peer.send(part);
+        simulation.advance(Duration::from_millis(1));
+    }
+    simulation.advance(Duration::from_millis(10));
+    assert_eq!(readAll(&mut stream), data());
+    assert_eq!(tcp(&peer.recv().expect("ACK").1).acknowledgementNumber, 201);
+
+    let stats = interface.stats();
+    assert_eq!((stats.reasmReqds, stats.reasmOKs, stats.reasmFails), (5, 1, 0));
+}
+
+#[test]
+fn duplicates_are_ignored_and_overlaps_discard_datagram() {
+    let (mut simulation, interface, peer, mut stream) = connect();
+    let parts = fragments(&segment(40000, 101, 1, "PA", &data()), 7, 40);
+    for part in [&parts[0], &parts[0], &parts[1], &parts[2]] {
+        peer.send(part);
+    }
+    simulation.advance(Duration::from_millis(10));
+    assert_eq!(readAll(&mut stream), data());
+
+    // Second fragment re-cut to start inside the first one
+    let parts = fragments(&segment(40000, 201, 1, "PA", &data()), 8, 40);
+    let overlapping = &fragments(&segment(40000, 201, 1, "PA", &data()), 8, 32)[1];
+    for part in [&parts[0], overlapping, &parts[1], &parts[2]] {
+        peer.send(part);
+    }
+    simulation.advance(Duration::from_millis(10));
+    assert!(readAll(&mut stream).is_empty());
+    let stats = interface.stats();
+    assert_eq!((stats.reasmOKs, stats.reasmFails), (1, 1));
+}
+
+#[test]
+fn incomplete_datagrams_time_out() {
+    let (mut simulation, interface, peer, mut stream) = connect();
+    let parts = fragments(&segment(40000, 101, 1, "PA", &data()), 3, 40);
+    peer.send(&parts[0]);
+    peer.send(&parts[1]);
+    simulation.advance(Duration::from_secs(31));
+    assert_eq!(interface.stats().reasmFails, 1);
+
+    // Rest of the datagram arrives too late to complete it
+    peer.send(&parts[2]);
+    simulation.advance(Duration::from_millis(10));
+    assert!(readAll(&mut stream).is_empty());
+    assert_eq!(interface.stats().reasmOKs, 0);
+}
+
+#[test]
+fn oldest_datagrams_are_evicted_over_limits() {
+    let (mut simulation, interface, peer, mut stream) = connect();
+    let packet = segment(40000, 101, 1, "PA", &data());
+    for id in 0..100 {
+        peer.send(&fragments(&packet, id, 40)[0]);
+    }
+    simulation.advance(Duration::from_millis(10));
+    assert_eq!(interface.stats().reasmFails, 36);
+
+    // Evicted datagram can't complete, the newest ones still can
+    for id in [0, 99] {
+        for part in &fragments(&packet, id, 40)[1..] {
+            peer.send(part);
+        }
+    }
+    simulation.advance(Duration::from_millis(10));
+    assert_eq!(readAll(&mut stream), data());
+    assert_eq!(interface.stats().reasmOKs, 1);
+}
+
+#[test]
+fn invalid_fragments_are_discarded() {
+    let (mut simulation, interface, peer, mut stream) = connect();
+    let packet = segment(40000, 101, 1, "PA", &data());
+    // Fragment that is not a multiple of 8 bytes, with more to come
+    let mut parts = fragments(&packet, 5, 40);
+    let first = &mut parts[0];
+    first.truncate(50);
+    let mut view = Ipv4Packet::newUnchecked(&mut first[..]);
+    view.setTotalLength(50);
+    view.fillChecksum();
+    // Fragment reaching past 64KB
+    let mut huge = fragments(&packet, 6, 40).pop().unwrap();
+    let mut view = Ipv4Packet::newUnchecked(&mut huge[..]);
+    view.setFragmentOffset(8190);
+    view.fillChecksum();
+
+    peer.send(&parts[0]);
+    peer.send(&huge);
+    simulation.advance(Duration::from_millis(10));
+    assert!(readAll(&mut stream).is_empty());
+    assert_eq!(interface.stats().reasmFails, 2);
+}
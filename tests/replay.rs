@@ -0,0 +1,142 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use common::{pcap, syn, tcp, LOCAL};
+use TCP::{CaptureFormat, Interface, IPAddress, PcapReplay, TcpError};
+use std::convert::TryInto;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+#[test]
+fn replayed_syn_is_answered_with_syn_ack() {
+    let replay = PcapReplay::from_reader(&pcap(&[(0, syn(40000, 100))])[..], LOCAL).unwrap();
+    replay.set_speed(None).unwrap();
+    assert_eq!(replay.expected().len(), 0);
+
+    let mut interface = Interface::with_device(replay.clone()).unwrap();
+    let _listener = interface.bind((IPAddress::UNSPECIFIED, 9000)).unwrap();
+    replay.start();
+    replay.wait(Some(Duration::from_secs(5))).unwrap();
+
+    let responses = replay.responses();
+    assert_eq!(responses.len(), 1);
+    let tcph = tcp(&responses[0].data);
+    assert!(tcph.syn && tcph.ack);
+    assert_eq!(tcph.acknowledgementNumber, 101);
+    assert_eq!((tcph.sourcePort, tcph.destinationPort), (9000, 40000));
+}
+
+#[test]
+fn recorded_timing_is_kept() {
+    let replay = PcapReplay::from_reader(&pcap(&[(0, syn(40000, 100)), (200_000, syn(40001, 500))])[..], LOCAL).unwrap();
+    let mut interface = Interface::with_device(replay.clone()).unwrap();
+    let _listener = interface.bind((IPAddress::UNSPECIFIED, 9000)).unwrap();
+    replay.start();
+    replay.wait(Some(Duration::from_secs(5))).unwrap();
+
+    // A packet is never delivered before its recorded time, however slow the
+    // stack is, so only the lower bound of the second answer is checked
+    let responses = replay.responses();
+    assert_eq!(responses.len(), 2);
+    assert_eq!(tcp(&responses[0].data).destinationPort, 40000);
+    assert_eq!(tcp(&responses[1].data).destinationPort, 40001);
+    assert!(responses[0].time <= responses[1].time);
+    assert!(responses[1].time >= Duration::from_millis(200));
+}
+
+#[test]
+fn pcapng_capture_replays_with_direction() {
+    let replay = PcapReplay::from_reader(&pcap(&[(0, syn(40000, 100))])[..], LOCAL).unwrap();
+    replay.set_speed(None).unwrap();
+    let buffer = SharedBuffer::default();
+
+    let mut interface = Interface::with_device(replay.clone()).unwrap();
+    interface.start_capture(buffer.clone(), CaptureFormat::PcapNg).unwrap();
+    let _listener = interface.bind((IPAddress::UNSPECIFIED, 9000)).unwrap();
+    replay.start();
+    replay.wait(Some(Duration::from_secs(5))).unwrap();
+    interface.stop_capture().unwrap();
+
+    // Direction comes from the capture, not from `local`
+    let captured = buffer.0.lock().unwrap().clone();
+    let session = PcapReplay::from_reader(&captured[..], IPAddress::UNSPECIFIED).unwrap();
+    assert_eq!(session.expected().len(), 1);
+    assert_eq!(session.expected()[0].data, replay.responses()[0].data);
+
+    let replayed = session.clone();
+    session.set_speed(None).unwrap();
+    let mut interface = Interface::with_device(session).unwrap();
+    let _listener = interface.bind((IPAddress::UNSPECIFIED, 9000)).unwrap();
+    replayed.start();
+    replayed.wait(Some(Duration::from_secs(5))).unwrap();
+    assert_eq!(replayed.responses()[0].data, replayed.expected()[0].data);
+}
+
+#[test]
+fn pcap_capture_has_headers_and_records() {
+    let replay = PcapReplay::from_reader(&pcap(&[(0, syn(40000, 100))])[..], LOCAL).unwrap();
+    replay.set_speed(None).unwrap();
+    let buffer = SharedBuffer::default();
+
+    let mut interface = Interface::with_device(replay.clone()).unwrap();
+    interface.start_capture(buffer.clone(), CaptureFormat::Pcap).unwrap();
+    let _listener = interface.bind((IPAddress::UNSPECIFIED, 9000)).unwrap();
+    replay.start();
+    replay.wait(Some(Duration::from_secs(5))).unwrap();
+    interface.stop_capture().unwrap();
+
+    let captured = buffer.0.lock().unwrap().clone();
+    let word = |offset: usize| u32::from_le_bytes(captured[offset..offset + 4].try_into().unwrap());
+    assert!(captured.len() >= 24);
+    assert_eq!(word(0), 0xA1B2_C3D4);
+    assert_eq!(word(4), 4 << 16 | 2);
+    assert_eq!(word(16), 65535);
+    assert_eq!(word(20), 101);
+
+    // Received SYN then the SYN-ACK sent back, each with captured and original length
+    let expected = [syn(40000, 100), replay.responses()[0].data.clone()];
+    let mut offset = 24;
+    for packet in &expected {
+        assert!(word(offset + 4) < 1_000_000);
+        assert_eq!(word(offset + 8) as usize, packet.len());
+        assert_eq!(word(offset + 12) as usize, packet.len());
+        offset += 16;
+        assert_eq!(&captured[offset..offset + packet.len()], &packet[..]);
+        offset += packet.len();
+    }
+    assert_eq!(offset, captured.len());
+}
+
+#[test]
+fn unsupported_pcapng_timestamp_resolution_is_rejected() {
+    // Section header, then an interface with if_tsresol 10^-127 which no integer holds
+    let mut file = Vec::new();
+    for word in &[0x0A0D_0D0Au32, 28, 0x1A2B_3C4D, 1, 0xFFFF_FFFF, 0xFFFF_FFFF, 28] {
+        file.extend_from_slice(&word.to_le_bytes());
+    }
+    for word in &[1u32, 32, 101, 65535] {
+        file.extend_from_slice(&word.to_le_bytes());
+    }
+    file.extend_from_slice(&[9, 0, 1, 0, 0x7F, 0, 0, 0, 0, 0, 0, 0]);
+    file.extend_from_slice(&32u32.to_le_bytes());
+
+    match PcapReplay::from_reader(&file[..], LOCAL) {
+        Err(TcpError::InvalidInput(reason)) => assert_eq!(reason, "Unsupported pcapng timestamp resolution"),
+        Err(error) => panic!("Unexpected error {}", error),
+        Ok(_) => panic!("Resolution accepted"),
+    }
+}
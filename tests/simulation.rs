@@ -0,0 +1,226 @@
+#![allow(non_snake_case)]
+
+mod common;
+
+use common::{handshake, segment, segmentBetween, setup, syn, tcp, LOCAL, REMOTE};
+use TCP::{Endpoint, Keepalive, Simulation};
+use std::io::{ErrorKind, Read, Write};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Run until `peer` receives a packet
+fn expect(simulation: &mut Simulation, peer: &Endpoint, limit: Duration) -> (Duration, Vec<u8>) {
+    let mut received = None;
+    assert!(simulation.run_until(limit, || { received = peer.recv(); received.is_some() }), "Nothing received");
+    received.unwrap()
+}
+
+#[test]
+fn keepalive_runs_on_virtual_time() {
+    let (mut simulation, mut interface, peer, mut listener) = setup(7);
+    interface.set_keepalive(Some(Keepalive { idle: Duration::from_secs(10), interval: Duration::from_secs(1), probes: 3 }));
+
+    let mut stream = handshake(&mut simulation, &mut listener, &peer, 40000);
+    stream.set_nonblocking(true).unwrap();
+
+    // Three unanswered probes one interval apart after being idle, then a reset
+    for probe in 0..3 {
+        let (time, packet) = expect(&mut simulation, &peer, Duration::from_secs(20));
+        let expected = Duration::from_secs(10 + probe);
+        assert!(time >= expected && time < expected + Duration::from_millis(200), "Probe at {:?}", time);
+        assert!(tcp(&packet).ack && !tcp(&packet).rst);
+    }
+    let (time, packet) = expect(&mut simulation, &peer, Duration::from_secs(20));
+    assert!(time >= Duration::from_secs(13) && time < Duration::from_millis(13200));
+    assert!(tcp(&packet).rst);
+
+    let error = stream.read(&mut [0u8; 10]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::ConnectionAborted);
+    assert_eq!(interface.stats().outRsts, 1);
+}
+
+#[test]
+fn user_timeout_aborts_only_unacknowledged_segments() {
+    let (mut simulation, _interface, peer, mut listener) = setup(3);
+
+    let mut stream = handshake(&mut simulation, &mut listener, &peer, 40000);
+    stream.set_nonblocking(true).unwrap();
+    stream.set_user_timeout(Some(Duration::from_secs(5)));
+    assert_eq!(stream.user_timeout(), Some(Duration::from_secs(5)));
+
+    // Everything sent is acknowledged. Written data is only queued, so it is not timed.
+    assert_eq!(stream.write(b"hello").unwrap(), 5);
+    for seq in 101..121 {
+        peer.send(&segment(40000, seq, 1, "PA", b"x"));
+        simulation.advance(Duration::from_secs(1));
+        assert!(!tcp(&expect(&mut simulation, &peer, Duration::from_millis(10)).1).rst);
+    }
+    assert_eq!(stream.read(&mut [0u8; 30]).unwrap(), 20);
+
+    // SYN-ACK is never acknowledged
+    peer.send(&syn(40001, 100));
+    simulation.advance(Duration::from_millis(10));
+    let (sent, synAck) = peer.recv().unwrap();
+    assert!(tcp(&synAck).syn);
+    let mut unanswered = listener.accept().unwrap();
+    unanswered.set_nonblocking(true).unwrap();
+    unanswered.set_user_timeout(Some(Duration::from_secs(5)));
+    let (time, packet) = expect(&mut simulation, &peer, Duration::from_secs(10));
+    assert!(tcp(&packet).rst);
+    let waited = time - sent;
+    assert!(waited >= Duration::from_secs(5) && waited < Duration::from_millis(5200), "Reset after {:?}", waited);
+    assert_eq!(unanswered.read(&mut [0u8; 10]).unwrap_err().kind(), ErrorKind::TimedOut);
+    assert_eq!(unanswered.write(b"again").unwrap_err().kind(), ErrorKind::TimedOut);
+    assert_eq!(stream.read(&mut [0u8; 10]).unwrap_err().kind(), ErrorKind::WouldBlock);
+}
+
+#[test]
+fn abort_after_fin_exchange_is_not_connected() {
+    let (mut simulation, interface, peer, mut listener) = setup(3);
+    let stream = handshake(&mut simulation, &mut listener, &peer, 40000);
+
+    // Peer's FIN is answered with ACK and FIN, whose ACK closes the connection
+    peer.send(&segment(40000, 101, 1, "FA", &[]));
+    simulation.advance(Duration::from_millis(10));
+    while peer.recv().is_some() {}
+    peer.send(&segment(40000, 102, 2, "A", &[]));
+    simulation.advance(Duration::from_millis(10));
+
+    assert_eq!(stream.abort().unwrap_err().kind(), ErrorKind::NotConnected);
+    simulation.advance(Duration::from_millis(10));
+    assert!(peer.recv().is_none());
+    assert_eq!(interface.stats().outRsts, 0);
+}
+
+/// Advance virtual time in ticks while `blocked` waits in another thread.
+/// Returns the virtual time it took to finish and its result.
+fn runBlocked<T>(simulation: &mut Simulation, blocked: JoinHandle<T>) -> (Duration, T) {
+    let start = simulation.elapsed();
+    while !blocked.is_finished() {
+        assert!(simulation.elapsed() - start < Duration::from_secs(60), "Call never returned");
+        simulation.advance(Duration::from_millis(100));
+        thread::sleep(Duration::from_millis(1));
+    }
+    (simulation.elapsed() - start, blocked.join().unwrap())
+}
+
+#[test]
+fn blocked_calls_time_out_on_virtual_time() {
+    let (mut simulation, _interface, peer, mut listener) = setup(5);
+    let mut stream = handshake(&mut simulation, &mut listener, &peer, 40000);
+
+    // Accept
+    listener.set_nonblocking(false).unwrap();
+    let (waited, (listener, result)) = runBlocked(&mut simulation, thread::spawn(move || {
+        let result = listener.accept_timeout(Duration::from_secs(2)).map(|_| ());
+        (listener, result)
+    }));
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::TimedOut);
+    assert!(waited >= Duration::from_secs(2), "Accept returned after {:?}", waited);
+    drop(listener);
+
+    // Read
+    stream.set_read_timeout(Some(Duration::from_secs(3))).unwrap();
+    let (waited, (mut stream, result)) = runBlocked(&mut simulation, thread::spawn(move || {
+        let result = stream.read(&mut [0u8; 10]);
+        (stream, result)
+    }));
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::WouldBlock);
+    assert!(waited >= Duration::from_secs(3), "Read returned after {:?}", waited);
+
+    // Write fills the send buffer, then waits for room that never comes
+    stream.set_write_timeout(Some(Duration::from_secs(4))).unwrap();
+    let (waited, (mut stream, partial)) = runBlocked(&mut simulation, thread::spawn(move || {
+        let result = stream.write(&vec![7u8; 300 * 1024]);
+        (stream, result)
+    }));
+    assert_eq!(partial.unwrap(), 256 * 1024);
+    assert!(waited >= Duration::from_secs(4), "Write returned after {:?}", waited);
+    let (waited, (_, result)) = runBlocked(&mut simulation, thread::spawn(move || {
+        let result = stream.write(b"more");
+        (stream, result)
+    }));
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::WouldBlock);
+    assert!(waited >= Duration::from_secs(4), "Write returned after {:?}", waited);
+}
+
+#[test]
+fn accept_reports_why_nothing_was_accepted() {
+    let mut simulation = Simulation::new(5);
+    let mut interface = simulation.add_interface(LOCAL).unwrap();
+    let mut listener = interface.bind((LOCAL, 9000)).unwrap();
+    listener.set_nonblocking(true).unwrap();
+
+    let error = listener.accept().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::WouldBlock);
+    let inner = error.get_ref().and_then(|inner| inner.downcast_ref::<TCP::TcpError>());
+    assert!(matches!(inner, Some(TCP::TcpError::WouldBlock)));
+    assert_eq!(listener.accept_timeout(Duration::from_secs(1)).unwrap_err().kind(), ErrorKind::WouldBlock);
+}
+
+#[test]
+fn blocked_read_wakes_on_data_before_timeout() {
+    let (mut simulation, _interface, peer, mut listener) = setup(5);
+    let mut stream = handshake(&mut simulation, &mut listener, &peer, 40000);
+
+    stream.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+    peer.send(&segment(40000, 101, 1, "PA", b"hello"));
+    let (waited, result) = runBlocked(&mut simulation, thread::spawn(move || {
+        let mut buf = [0u8; 10];
+        stream.read(&mut buf).map(|len| buf[..len].to_vec())
+    }));
+    assert_eq!(result.unwrap(), b"hello");
+    assert!(waited < Duration::from_secs(30));
+}
+
+/// Arrival time and content of every packet the peer receives
+fn jitteredRun(seed: u64) -> Vec<(Duration, Vec<u8>)> {
+    let mut simulation = Simulation::new(seed);
+    simulation.set_latency(Duration::from_millis(5), Duration::from_millis(20));
+    let mut interface = simulation.add_interface(LOCAL).unwrap();
+    let peer = simulation.add_endpoint(REMOTE).unwrap();
+    let _listener = interface.bind((LOCAL, 9000)).unwrap();
+
+    for port in 40000..40010 {
+        peer.send(&syn(port, 100));
+    }
+    simulation.advance(Duration::from_secs(1));
+    std::iter::from_fn(|| peer.recv()).collect()
+}
+
+#[test]
+fn same_seed_gives_same_run() {
+    let run = jitteredRun(42);
+    assert_eq!(run.len(), 10);
+    assert_eq!(run, jitteredRun(42));
+    assert_ne!(run, jitteredRun(43));
+}
+
+#[test]
+fn packets_to_unknown_address_are_dropped() {
+    let mut simulation = Simulation::new(1);
+    let mut interface = simulation.add_interface(LOCAL).unwrap();
+    let _listener = interface.bind((LOCAL, 9000)).unwrap();
+    assert!(simulation.add_endpoint(LOCAL).is_err());
+
+    // Stranger's own SYN is answered to its address
+    let strangerAddr = TCP::IPAddress::new(10, 0, 0, 3);
+    let stranger = simulation.add_endpoint(strangerAddr).unwrap();
+    stranger.send(&segmentBetween(strangerAddr, LOCAL, 40000, 100, 0, "S", &[]));
+    simulation.advance(Duration::from_secs(1));
+    let (_, packet) = stranger.recv().unwrap();
+    assert!(tcp(&packet).syn && tcp(&packet).ack);
+
+    // SYN spoofing REMOTE is answered to REMOTE, which nobody has
+    stranger.send(&syn(40001, 100));
+    simulation.advance(Duration::from_secs(1));
+    assert!(stranger.recv().is_none());
+    assert_eq!(interface.stats().outSegs, 2);
+
+    // Packet to an address nobody has never reaches the interface
+    let inSegs = interface.stats().inSegs;
+    stranger.send(&segmentBetween(strangerAddr, TCP::IPAddress::new(10, 0, 0, 9), 40002, 100, 0, "S", &[]));
+    simulation.advance(Duration::from_secs(1));
+    assert_eq!(interface.stats().inSegs, inSegs);
+    assert_eq!(interface.stats().outSegs, 2);
+}